@@ -0,0 +1,241 @@
+use app_lib::game::{Game, BLACK, WHITE};
+use app_lib::go::{empty_board, play_move, Intersection, Position, Stones, Zobrist};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SIZE: usize = 19;
+const MOVES: usize = 250;
+
+/// The board before bitboards, where every stone keeps its group as sets of intersections and
+/// liberties, kept here to compare against
+mod legacy {
+    use std::collections::HashSet;
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    pub enum Intersection {
+        Empty,
+        Black(Group),
+        White(Group),
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    pub struct Group {
+        pub intersections: HashSet<(usize, usize)>,
+        pub liberties: HashSet<(usize, usize)>,
+    }
+
+    fn stone(color: usize) -> Intersection {
+        let group = Group {
+            intersections: HashSet::new(),
+            liberties: HashSet::new(),
+        };
+        match color {
+            1 => Intersection::Black(group),
+            _ => Intersection::White(group),
+        }
+    }
+
+    fn neighbors(x: usize, y: usize, rows: usize, cols: usize) -> Vec<(usize, usize)> {
+        let mut neighbors = vec![];
+        if x > 0 {
+            neighbors.push((x - 1, y));
+        }
+        if x < rows - 1 {
+            neighbors.push((x + 1, y));
+        }
+        if y > 0 {
+            neighbors.push((x, y - 1));
+        }
+        if y < cols - 1 {
+            neighbors.push((x, y + 1));
+        }
+        neighbors
+    }
+
+    fn group(intersection: &Intersection, color: usize) -> Option<&Group> {
+        match (intersection, color) {
+            (Intersection::Black(group), 1) | (Intersection::White(group), 2) => Some(group),
+            _ => None,
+        }
+    }
+
+    pub fn get_liberties(x: usize, y: usize, color: usize, board: &mut [Vec<Intersection>]) {
+        let (rows, cols) = (board.len(), board[0].len());
+        let intersections = match group(&board[x][y], color) {
+            Some(group) => group.intersections.clone(),
+            None => return,
+        };
+        let mut liberties = HashSet::new();
+        for &(i, j) in intersections.iter() {
+            for (row, col) in neighbors(i, j, rows, cols) {
+                if board[row][col] == Intersection::Empty {
+                    liberties.insert((row, col));
+                }
+            }
+        }
+        for &(i, j) in intersections.iter() {
+            if let Intersection::Black(group) | Intersection::White(group) = &mut board[i][j] {
+                group.liberties = liberties.clone();
+            }
+        }
+    }
+
+    pub fn get_intersections(x: usize, y: usize, color: usize, board: &mut [Vec<Intersection>]) {
+        let (rows, cols) = (board.len(), board[0].len());
+        let mut intersections = HashSet::new();
+        let mut visited = vec![vec![false; cols]; rows];
+        let mut queue = vec![(x, y)];
+        while let Some((x, y)) = queue.pop() {
+            if visited[x][y] || group(&board[x][y], color).is_none() {
+                continue;
+            }
+            visited[x][y] = true;
+            intersections.insert((x, y));
+            queue.extend(neighbors(x, y, rows, cols));
+        }
+        for &(i, j) in intersections.iter() {
+            if let Intersection::Black(group) | Intersection::White(group) = &mut board[i][j] {
+                group.intersections = intersections.clone();
+            }
+        }
+    }
+
+    pub fn play_move(x: usize, y: usize, color: usize, board: &mut [Vec<Intersection>]) -> usize {
+        let (rows, cols) = (board.len(), board[0].len());
+        board[x][y] = stone(color);
+        get_intersections(x, y, color, board);
+        get_liberties(x, y, color, board);
+
+        let opponent = if color == 1 { 2 } else { 1 };
+        let mut captured: Vec<(usize, usize)> = vec![];
+        for (row, col) in neighbors(x, y, rows, cols) {
+            if captured.contains(&(row, col)) {
+                continue;
+            }
+            get_liberties(row, col, opponent, board);
+            if let Some(group) = group(&board[row][col], opponent) {
+                if group.liberties.is_empty() {
+                    captured.extend(group.intersections.iter());
+                }
+            }
+        }
+        for &(row, col) in captured.iter() {
+            board[row][col] = Intersection::Empty;
+        }
+        captured.len()
+    }
+
+    /// Check that a move is on an empty intersection and is not suicide, working on a copy of
+    /// the board as the ko check did
+    pub fn validate(x: usize, y: usize, color: usize, board: &[Vec<Intersection>]) -> bool {
+        if board[x][y] != Intersection::Empty {
+            return false;
+        }
+        let (rows, cols) = (board.len(), board[0].len());
+        let mut board = board.to_vec();
+        let opponent = if color == 1 { 2 } else { 1 };
+        let mut is_valid = false;
+        for (row, col) in neighbors(x, y, rows, cols) {
+            if board[row][col] == Intersection::Empty {
+                is_valid = true;
+            }
+            get_liberties(row, col, color, &mut board);
+            if group(&board[row][col], color).map_or(false, |group| group.liberties.len() > 1) {
+                is_valid = true;
+            }
+            get_liberties(row, col, opponent, &mut board);
+            if group(&board[row][col], opponent).map_or(false, |group| group.liberties.len() == 1) {
+                is_valid = true;
+            }
+        }
+        is_valid
+    }
+}
+
+/// Find the moves of a game, choosing empty intersections where a stone would not be suicide
+fn game_moves() -> Vec<(usize, usize, usize)> {
+    let mut board = empty_board(SIZE, SIZE);
+    let mut moves = vec![];
+    let mut seed: u64 = 1;
+    while moves.len() < MOVES {
+        // a fixed linear congruential generator keeps the game the same between runs
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let point = (seed >> 33) as usize % (SIZE * SIZE);
+        let (x, y) = (point / SIZE, point % SIZE);
+        let color = if moves.len() % 2 == 0 { BLACK } else { WHITE };
+        if board[x][y] != Intersection::Empty {
+            continue;
+        }
+        let mut stones = Stones::from_board(&board);
+        if !stones.place(x, y, color).1.is_empty() {
+            continue;
+        }
+        play_move(x, y, color, &mut board);
+        moves.push((x, y, color));
+    }
+    moves
+}
+
+fn play_game(c: &mut Criterion) {
+    let moves = game_moves();
+    let mut group = c.benchmark_group("play game");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            let mut board = vec![vec![legacy::Intersection::Empty; SIZE]; SIZE];
+            for &(x, y, color) in moves.iter() {
+                black_box(legacy::play_move(x, y, color, &mut board));
+            }
+        })
+    });
+    group.bench_function("bitboard", |b| {
+        b.iter(|| {
+            let mut stones = Stones::new(SIZE, SIZE);
+            for &(x, y, color) in moves.iter() {
+                black_box(stones.place(x, y, color));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn validate_all(c: &mut Criterion) {
+    // set up the middle of the game, then check every intersection for the player to move
+    let moves = game_moves();
+    let mut board = empty_board(SIZE, SIZE);
+    let mut legacy_board = vec![vec![legacy::Intersection::Empty; SIZE]; SIZE];
+    let mut game = Game::new(SIZE, SIZE);
+    let mut hash = Zobrist::new(SIZE, SIZE);
+    for &(x, y, color) in moves.iter().take(MOVES / 2) {
+        let (captured, suicide) = play_move(x, y, color, &mut board);
+        legacy::play_move(x, y, color, &mut legacy_board);
+        let new_hash = hash.update(&board);
+        let removed = captured.into_iter().chain(suicide).collect();
+        game.add_node(&board, new_hash, Some((x, y)), color, (0, 0), removed);
+    }
+    let color = if MOVES / 2 % 2 == 0 { BLACK } else { WHITE };
+
+    let mut group = c.benchmark_group("validate every intersection");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            for x in 0..SIZE {
+                for y in 0..SIZE {
+                    black_box(legacy::validate(x, y, color, &legacy_board));
+                }
+            }
+        })
+    });
+    // the position checks ko and superko against the game history as well, as validate does
+    group.bench_function("position", |b| {
+        b.iter(|| {
+            for x in 0..SIZE {
+                for y in 0..SIZE {
+                    let position = Position::new(&board, &hash, &game.info.ruleset, game.history());
+                    black_box(position.play(color, (x, y)).is_ok());
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, play_game, validate_all);
+criterion_main!(benches);
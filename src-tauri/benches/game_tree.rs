@@ -0,0 +1,79 @@
+use app_lib::game::{Annotation, Game, NodeId, BLACK};
+use app_lib::go::{play_move, Intersection, Zobrist};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SIZE: usize = 19;
+const MOVES: usize = 300;
+const VARIATION_EVERY: usize = 10;
+const VARIATION_MOVES: usize = 30;
+
+/// Play `moves` pseudo-random moves from the current node of the game, commenting on each one
+fn play_line(game: &mut Game, hash: &mut Zobrist, mut seed: u64, moves: usize) {
+    let mut board = game.get_board(game.curr);
+    hash.update(&board);
+    let mut played = 0;
+    while played < moves {
+        // a fixed linear congruential generator keeps the records the same between runs
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let point = (seed >> 33) as usize % (SIZE * SIZE);
+        let (x, y) = (point / SIZE, point % SIZE);
+        if board[x][y] != Intersection::Empty {
+            continue;
+        }
+        let color = game.to_play();
+        let (captured, suicide) = play_move(x, y, color, &mut board);
+        let prisoners = match color {
+            BLACK => (captured.len(), suicide.len()),
+            _ => (suicide.len(), captured.len()),
+        };
+        let mut removed = captured;
+        removed.extend(suicide);
+        let new_hash = hash.update(&board);
+        game.add_node(&board, new_hash, Some((x, y)), color, prisoners, removed);
+        game.curr_node_mut().set_annotation(Annotation {
+            comment: Some(format!("move {} at {}-{}", played + 1, x, y)),
+            ..Annotation::default()
+        });
+        played += 1;
+    }
+}
+
+/// Build a long game with a variation branching off every few moves of the main line
+fn big_record() -> Game {
+    let mut game = Game::new(SIZE, SIZE);
+    let mut hash = Zobrist::new(SIZE, SIZE);
+    play_line(&mut game, &mut hash, 1, MOVES);
+
+    let main_line: Vec<NodeId> = game.preorder().into_iter().take(MOVES + 1).collect();
+    for (i, &node) in main_line.iter().enumerate().step_by(VARIATION_EVERY) {
+        game.curr = node;
+        play_line(&mut game, &mut hash, i as u64 + 2, VARIATION_MOVES);
+    }
+
+    // end on the first line played, which comes first at every branch as the main line in SGF
+    game.curr = main_line[MOVES];
+    game
+}
+
+fn game_tree(c: &mut Criterion) {
+    let game = big_record();
+    let end = game.curr;
+
+    c.bench_function("build record", |b| b.iter(big_record));
+    c.bench_function("board at end of main line", |b| {
+        b.iter(|| black_box(game.get_board(black_box(end))))
+    });
+    c.bench_function("replay main line", |b| {
+        let main_line: Vec<NodeId> = game.preorder().into_iter().take(MOVES + 1).collect();
+        b.iter(|| {
+            for &node in main_line.iter() {
+                black_box(game.get_board(node));
+            }
+        })
+    });
+    c.bench_function("tree layout", |b| b.iter(|| black_box(game.get_tree())));
+    c.bench_function("write sgf", |b| b.iter(|| black_box(game.to_sgf())));
+}
+
+criterion_group!(benches, game_tree);
+criterion_main!(benches);
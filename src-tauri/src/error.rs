@@ -0,0 +1,80 @@
+use crate::go::IllegalMove;
+use crate::sgf::SgfError;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::sync::PoisonError;
+
+/// An error from a command, sent to the frontend as its message
+#[derive(Debug)]
+pub enum Error {
+    /// a file could not be read or written
+    Io(std::io::Error),
+    /// a saved game could not be converted to or from JSON
+    Json(serde_json::Error),
+    /// an SGF string could not be parsed
+    Sgf(SgfError),
+    /// an SGF string was parsed, but does not describe a valid game
+    InvalidSgf(String),
+    /// an SGF collection has no game at an index
+    GameNotFound(usize),
+    /// an intersection is not on the board
+    OutOfBounds(usize, usize),
+    /// a move breaks the rules
+    IllegalMove(IllegalMove),
+    /// the state of the game was left unusable by an earlier failure
+    Poisoned,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "Cannot access file: {}", err),
+            Error::Json(err) => write!(f, "Invalid saved game: {}", err),
+            Error::Sgf(err) => write!(f, "Invalid SGF: {}", err),
+            Error::InvalidSgf(message) => write!(f, "Invalid SGF: {}", message),
+            Error::GameNotFound(index) => write!(f, "The file has no game number {}", index + 1),
+            Error::OutOfBounds(x, y) => write!(f, "Intersection ({}, {}) is not on the board", x, y),
+            Error::IllegalMove(reason) => write!(f, "Illegal move: {}", reason),
+            Error::Poisoned => write!(f, "The game is unavailable after an earlier failure"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<SgfError> for Error {
+    fn from(err: SgfError) -> Error {
+        Error::Sgf(err)
+    }
+}
+
+impl From<IllegalMove> for Error {
+    fn from(reason: IllegalMove) -> Error {
+        Error::IllegalMove(reason)
+    }
+}
+
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Error {
+        Error::Poisoned
+    }
+}
+
+// commands send errors to the frontend as their message
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
@@ -1,240 +1,1648 @@
-use crate::go::{Intersection, Zobrist};
+use crate::go::{empty_board, play_move, set_stones, Intersection, PastPosition, Zobrist};
+use crate::rules::Ruleset;
+use crate::score::get_territory;
+use crate::sgf::{escape_text, sgf_point};
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
 
-pub const ROWS: usize = 19;
-pub const COLS: usize = 19;
+pub const EMPTY: usize = 0;
 pub const BLACK: usize = 1;
 pub const WHITE: usize = 2;
 
-pub enum Node {
-    Move {
-        board: Vec<Vec<Intersection>>,
-        piece: (usize, usize),
-        color: usize,
-        parent: Option<Arc<Mutex<Node>>>,
-        children: Vec<Arc<Mutex<Node>>>,
-    },
-    End {
-        board: Vec<Vec<Intersection>>,
-        piece: (usize, usize),
-        color: usize,
-        parent: Option<Arc<Mutex<Node>>>,
-    },
+/// Stones added or removed at a node without playing a move, as in the SGF AB, AW, AE and PL
+/// properties
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Setup {
+    pub black: Vec<(usize, usize)>,
+    pub white: Vec<(usize, usize)>,
+    pub empty: Vec<(usize, usize)>,
+    /// the player to move after the setup, if it is given
+    pub player: Option<usize>,
+}
+
+impl Setup {
+    /// Get the setup that turns one board into another
+    pub fn diff(
+        before: &[Vec<Intersection>],
+        after: &[Vec<Intersection>],
+        player: Option<usize>,
+    ) -> Setup {
+        let mut setup = Setup {
+            player,
+            ..Setup::default()
+        };
+        for i in 0..after.len() {
+            for j in 0..after[i].len() {
+                match (&before[i][j], &after[i][j]) {
+                    (Intersection::Black, Intersection::Black) => (),
+                    (Intersection::White, Intersection::White) => (),
+                    (Intersection::Empty, Intersection::Empty) => (),
+                    (_, Intersection::Black) => setup.black.push((i, j)),
+                    (_, Intersection::White) => setup.white.push((i, j)),
+                    (_, Intersection::Empty) => setup.empty.push((i, j)),
+                }
+            }
+        }
+        setup
+    }
+
+    /// Check if the setup changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.black.is_empty() && self.white.is_empty() && self.empty.is_empty() && self.player.is_none()
+    }
+
+    /// Convert the setup to SGF properties
+    pub fn to_sgf(&self) -> String {
+        let mut sgf = String::new();
+        for (prop, points) in [("AB", &self.black), ("AW", &self.white), ("AE", &self.empty)] {
+            if !points.is_empty() {
+                sgf.push_str(prop);
+                for &point in points {
+                    sgf.push_str(&format!("[{}]", sgf_point(Some(point))));
+                }
+            }
+        }
+        match self.player {
+            Some(BLACK) => sgf.push_str("PL[B]"),
+            Some(WHITE) => sgf.push_str("PL[W]"),
+            _ => (),
+        }
+        sgf
+    }
+}
+
+/// How strongly an annotation applies, as in the SGF Double type
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Emphasis {
+    Normal,
+    Strong,
+}
+
+impl Emphasis {
+    /// Parse an SGF Double value, where anything but 2 is normal
+    pub fn from_sgf(value: &str) -> Emphasis {
+        if value.trim() == "2" {
+            Emphasis::Strong
+        } else {
+            Emphasis::Normal
+        }
+    }
+
+    pub fn to_sgf(&self) -> &'static str {
+        match self {
+            Emphasis::Normal => "1",
+            Emphasis::Strong => "2",
+        }
+    }
+}
+
+/// An annotation of the move at a node
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MoveAnnotation {
+    /// BM
+    Bad,
+    /// TE
+    Tesuji,
+    /// DO
+    Doubtful,
+    /// IT
+    Interesting,
+}
+
+/// A judgement of the position at a node
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Judgement {
+    /// GB
+    GoodForBlack,
+    /// GW
+    GoodForWhite,
+    /// DM
+    Even,
+    /// UC
+    Unclear,
+}
+
+/// Text and annotations attached to a node
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// C
+    pub comment: Option<String>,
+    /// N
+    pub name: Option<String>,
+    pub move_annotation: Option<(MoveAnnotation, Emphasis)>,
+    pub judgement: Option<(Judgement, Emphasis)>,
+    /// HO, marking the node as a hotspot
+    pub hotspot: Option<Emphasis>,
+}
+
+impl Annotation {
+    /// Convert the annotation to SGF properties
+    pub fn to_sgf(&self) -> String {
+        let mut sgf = String::new();
+        if let Some(comment) = &self.comment {
+            sgf.push_str(&format!("C[{}]", escape_text(comment)));
+        }
+        if let Some(name) = &self.name {
+            sgf.push_str(&format!("N[{}]", escape_text(name)));
+        }
+
+        // doubtful and interesting moves have no value
+        match self.move_annotation {
+            Some((MoveAnnotation::Bad, emphasis)) => sgf.push_str(&format!("BM[{}]", emphasis.to_sgf())),
+            Some((MoveAnnotation::Tesuji, emphasis)) => sgf.push_str(&format!("TE[{}]", emphasis.to_sgf())),
+            Some((MoveAnnotation::Doubtful, _)) => sgf.push_str("DO[]"),
+            Some((MoveAnnotation::Interesting, _)) => sgf.push_str("IT[]"),
+            None => (),
+        }
+        if let Some((judgement, emphasis)) = self.judgement {
+            let id = match judgement {
+                Judgement::GoodForBlack => "GB",
+                Judgement::GoodForWhite => "GW",
+                Judgement::Even => "DM",
+                Judgement::Unclear => "UC",
+            };
+            sgf.push_str(&format!("{}[{}]", id, emphasis.to_sgf()));
+        }
+        if let Some(emphasis) = self.hotspot {
+            sgf.push_str(&format!("HO[{}]", emphasis.to_sgf()));
+        }
+        sgf
+    }
+}
+
+/// A mark drawn on the board at a node
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Markup {
+    /// TR
+    Triangle((usize, usize)),
+    /// SQ
+    Square((usize, usize)),
+    /// CR
+    Circle((usize, usize)),
+    /// MA
+    Cross((usize, usize)),
+    /// LB
+    Label((usize, usize), String),
+    /// AR, from the first point to the second
+    Arrow((usize, usize), (usize, usize)),
+    /// LN
+    Line((usize, usize), (usize, usize)),
+    /// DD, for a part of the board that is dimmed
+    Dim((usize, usize)),
+}
+
+impl Markup {
+    /// Get the intersection of a mark on a single point, which can hold only one such mark
+    pub fn get_point(&self) -> Option<(usize, usize)> {
+        match self {
+            Markup::Triangle(point)
+            | Markup::Square(point)
+            | Markup::Circle(point)
+            | Markup::Cross(point) => Some(*point),
+            _ => None,
+        }
+    }
+}
+
+/// Convert the markup of a node to SGF properties, grouping the marks of each kind
+pub fn markup_to_sgf(markup: &[Markup]) -> String {
+    let mut sgf = String::new();
+    for id in ["TR", "SQ", "CR", "MA", "LB", "AR", "LN", "DD"] {
+        let mut values = String::new();
+        for mark in markup {
+            let value = match (id, mark) {
+                ("TR", Markup::Triangle(point))
+                | ("SQ", Markup::Square(point))
+                | ("CR", Markup::Circle(point))
+                | ("MA", Markup::Cross(point))
+                | ("DD", Markup::Dim(point)) => sgf_point(Some(*point)),
+                ("LB", Markup::Label(point, text)) => {
+                    format!("{}:{}", sgf_point(Some(*point)), escape_text(text))
+                }
+                ("AR", Markup::Arrow(from, to)) | ("LN", Markup::Line(from, to)) => {
+                    format!("{}:{}", sgf_point(Some(*from)), sgf_point(Some(*to)))
+                }
+                _ => continue,
+            };
+            values.push_str(&format!("[{}]", value));
+        }
+        if !values.is_empty() {
+            sgf.push_str(&format!("{}{}", id, values));
+        }
+    }
+    sgf
+}
+
+/// Index of a node in the arena of a game tree, which also identifies the node, including
+/// across saves
+pub type NodeId = usize;
+
+/// Number of nodes between nodes that keep the whole board, so that the board at any node is
+/// rebuilt by replaying at most this many nodes
+const CHECKPOINT_INTERVAL: usize = 32;
+/// Largest node id taken from a saved game, so that a damaged file cannot make the arena of
+/// nodes too large to allocate
+const MAX_NODE_ID: usize = 1 << 20;
+
+/// A node of the game tree, storing only what changed on the board since its parent
+#[derive(Debug, Clone)]
+pub struct Node {
+    hash: u64,
+    piece: Option<(usize, usize)>,
+    color: usize,
+    captures: (usize, usize),
+    /// stones removed from the board by the move, which are the captured stones or the group
+    /// of a suicide
+    removed: Vec<(usize, usize)>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// index of the child followed when redoing
+    main_line: usize,
+    dead: HashSet<(usize, usize)>,
+    /// stones changed without a move, where a node with no move and no setup is a pass
+    setup: Option<Setup>,
+    annotation: Annotation,
+    markup: Vec<Markup>,
+    /// the whole board, kept every few nodes to rebuild the boards of the nodes below
+    checkpoint: Option<Vec<Vec<Intersection>>>,
+    /// number of nodes from the nearest node above that keeps the whole board
+    distance: usize,
 }
 
 impl Node {
-    pub fn new(
-        board: Vec<Vec<Intersection>>,
-        piece: (usize, usize),
+    fn new(
+        hash: u64,
+        piece: Option<(usize, usize)>,
         color: usize,
-        parent: Option<Arc<Mutex<Node>>>,
+        captures: (usize, usize),
+        removed: Vec<(usize, usize)>,
+        parent: Option<NodeId>,
     ) -> Node {
-        Node::Move {
-            board,
+        Node {
+            hash,
             piece,
             color,
+            captures,
+            removed,
             parent,
             children: vec![],
+            main_line: 0,
+            dead: HashSet::new(),
+            setup: None,
+            annotation: Annotation::default(),
+            markup: vec![],
+            checkpoint: None,
+            distance: 0,
         }
     }
 
-    pub fn end(
-        board: Vec<Vec<Intersection>>,
-        piece: (usize, usize),
-        color: usize,
-        parent: Option<Arc<Mutex<Node>>>,
-    ) -> Node {
-        Node::End {
-            board,
-            piece,
-            color,
-            parent,
+    /// Get the Zobrist hash of the board at this node
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Get the prisoners taken by (black, white) up to and including this node
+    pub fn get_captures(&self) -> (usize, usize) {
+        self.captures
+    }
+
+    /// Get the color of the player who made the move at this node
+    pub fn get_color(&self) -> usize {
+        self.color
+    }
+
+    /// Get the intersection played at this node, which is None for passes and setup nodes
+    pub fn get_piece(&self) -> Option<(usize, usize)> {
+        self.piece
+    }
+
+    /// Get the parent of this node, which is None for the root
+    pub fn get_parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// Get the children of this node, in the order they were added
+    pub fn get_children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// Get the index of the child followed when redoing
+    pub fn get_main_line(&self) -> usize {
+        self.main_line
+    }
+
+    /// Set the child followed when redoing, returning false if there is no child at the index
+    pub fn set_main_line(&mut self, index: usize) -> bool {
+        if index < self.children.len() {
+            self.main_line = index;
+            true
+        } else {
+            false
         }
     }
 
-    pub fn get_board(&self) -> Vec<Vec<Intersection>> {
-        match self {
-            Node::Move { board, .. } => board.clone(),
-            Node::End { board, .. } => board.clone(),
+    /// Get the stones marked as dead at this node
+    pub fn get_dead(&self) -> HashSet<(usize, usize)> {
+        self.dead.clone()
+    }
+
+    /// Toggle a group of stones between dead and alive at this node
+    pub fn toggle_dead(&mut self, group: &HashSet<(usize, usize)>) {
+        if group.is_subset(&self.dead) {
+            self.dead.retain(|i| !group.contains(i));
+        } else {
+            self.dead.extend(group.iter());
+        }
+    }
+
+    /// Get the stones added or removed at this node without a move
+    pub fn get_setup(&self) -> Setup {
+        self.setup.clone().unwrap_or_default()
+    }
+
+    /// Get the comment, name and annotations of this node
+    pub fn get_annotation(&self) -> Annotation {
+        self.annotation.clone()
+    }
+
+    /// Replace the comment, name and annotations of this node
+    pub fn set_annotation(&mut self, new_annotation: Annotation) {
+        self.annotation = new_annotation;
+    }
+
+    /// Get the marks drawn on the board at this node
+    pub fn get_markup(&self) -> Vec<Markup> {
+        self.markup.clone()
+    }
+
+    /// Replace the marks drawn on the board at this node
+    pub fn set_markup(&mut self, new_markup: Vec<Markup>) {
+        self.markup = new_markup;
+    }
+
+    /// Add a mark at this node, replacing any other single point mark on the same intersection
+    pub fn add_markup(&mut self, mark: Markup) {
+        if let Some(point) = mark.get_point() {
+            self.markup.retain(|other| other.get_point() != Some(point));
+        }
+        if let Markup::Label(point, _) = mark {
+            self.markup
+                .retain(|other| !matches!(other, Markup::Label(other, _) if *other == point));
         }
+        if !self.markup.contains(&mark) {
+            self.markup.push(mark);
+        }
+    }
+
+    /// Remove a mark at this node
+    pub fn remove_markup(&mut self, mark: &Markup) {
+        self.markup.retain(|other| other != mark);
+    }
+
+    /// Check if this node is a setup node, which may change the board without a move
+    pub fn is_setup(&self) -> bool {
+        self.piece.is_none() && self.setup.is_some()
+    }
+
+    /// Check if this node is a pass, which has no piece and is not the root or a setup node
+    pub fn is_pass(&self) -> bool {
+        self.piece.is_none() && self.parent.is_some() && !self.is_setup()
+    }
+
+    /// Make the changes of this node on the board of its parent
+    fn apply(&self, board: &mut [Vec<Intersection>]) {
+        if let Some(setup) = &self.setup {
+            set_stones(&setup.empty, EMPTY, board);
+            set_stones(&setup.black, BLACK, board);
+            set_stones(&setup.white, WHITE, board);
+        }
+        if let Some(piece) = self.piece {
+            set_stones(&[piece], self.color, board);
+        }
+        set_stones(&self.removed, EMPTY, board);
     }
 }
 
+/// A child of a node, as listed for choosing a variation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variation {
+    pub piece: Option<(usize, usize)>,
+    pub color: usize,
+    /// whether this child is a setup node rather than a move or pass
+    pub setup: bool,
+    /// whether redo follows this child
+    pub main_line: bool,
+}
+
+/// A node of the game tree, as laid out for drawing the variation tree, with nodes listed
+/// in preorder and referring to each other by id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub id: usize,
+    pub path: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// number of moves and passes from the root
+    pub move_number: usize,
+    pub color: usize,
+    pub piece: Option<(usize, usize)>,
+    pub setup: bool,
+    pub has_comment: bool,
+    /// column to draw the node in, which is its distance from the root
+    pub depth: usize,
+    /// row to draw the node in, where the first child of a node shares its row
+    pub row: usize,
+}
+
+/// The whole game tree, laid out for drawing the variation tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeView {
+    pub nodes: Vec<TreeNode>,
+    /// id of the current node
+    pub current: usize,
+}
+
 /// Struct to represent saved game
 #[derive(Serialize, Deserialize)]
 pub struct Saved {
     pub sgf: String,
-    states: Vec<(Vec<Vec<Intersection>>, Zobrist)>,
+    #[serde(default)]
+    pub info: Option<GameInfo>,
+    /// ids of the nodes in preorder, given back to the nodes when loading
+    #[serde(default)]
+    ids: Vec<usize>,
+    /// ids of the nodes of the saved states
+    #[serde(default)]
+    state_nodes: Vec<usize>,
+    /// boards of the saved states in files saved before nodes had ids
+    #[serde(default, skip_serializing)]
+    states: Vec<(Vec<Vec<SavedIntersection>>, IgnoredAny)>,
+}
+
+/// An intersection in files saved when stones still kept their group
+#[derive(Deserialize)]
+enum SavedIntersection {
+    Empty,
+    Black(IgnoredAny),
+    White(IgnoredAny),
 }
 
 impl Saved {
     pub fn new(game: &Game) -> Saved {
         let sgf: String = game.to_sgf();
-        let states: Vec<(Vec<Vec<Intersection>>, Zobrist)> = game
-            .states
-            .iter()
-            .map(|(board, _, _, hash)| (board.clone(), hash.clone()))
-            .collect();
-        Saved { sgf, states }
+        let ids = game.preorder();
+        let state_nodes = game.states.iter().map(|&(node, _)| node).collect();
+        Saved {
+            sgf,
+            info: Some(game.info.clone()),
+            ids,
+            state_nodes,
+            states: Vec::new(),
+        }
+    }
+}
+
+/// Information about a game and its players, stored in the SGF root properties
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameInfo {
+    /// name of the black player (PB)
+    pub black: Option<String>,
+    /// name of the white player (PW)
+    pub white: Option<String>,
+    /// rank of the black player (BR)
+    pub black_rank: Option<String>,
+    /// rank of the white player (WR)
+    pub white_rank: Option<String>,
+    /// points given to white (KM)
+    pub komi: f64,
+    /// dates the game was played (DT)
+    pub date: Option<String>,
+    /// event the game was played at (EV)
+    pub event: Option<String>,
+    /// result of the game, such as B+3.5 (RE)
+    pub result: Option<String>,
+    /// rules the game is played under (RU)
+    pub ruleset: Ruleset,
+    /// name of the game (GN)
+    pub name: Option<String>,
+    /// place the game was played (PC)
+    pub place: Option<String>,
+}
+
+impl GameInfo {
+    /// Convert the game information to SGF root properties
+    pub fn to_sgf(&self) -> String {
+        let mut sgf = format!("RU[{}]KM[{}]", escape_text(&self.ruleset.name), self.komi);
+        let properties = [
+            ("PB", &self.black),
+            ("BR", &self.black_rank),
+            ("PW", &self.white),
+            ("WR", &self.white_rank),
+            ("DT", &self.date),
+            ("EV", &self.event),
+            ("GN", &self.name),
+            ("PC", &self.place),
+            ("RE", &self.result),
+        ];
+        for (id, value) in properties {
+            if let Some(value) = value {
+                sgf.push_str(&format!("{}[{}]", id, escape_text(value)));
+            }
+        }
+        sgf
+    }
+}
+
+impl Default for GameInfo {
+    fn default() -> GameInfo {
+        let ruleset = Ruleset::default();
+        GameInfo {
+            black: None,
+            white: None,
+            black_rank: None,
+            white_rank: None,
+            komi: ruleset.komi,
+            date: None,
+            event: None,
+            result: None,
+            ruleset,
+            name: None,
+            place: None,
+        }
+    }
+}
+
+/// Iterator over the positions from a node back to the start of the game
+#[derive(Clone)]
+pub struct History<'a> {
+    game: &'a Game,
+    node: Option<NodeId>,
+}
+
+impl<'a> Iterator for History<'a> {
+    type Item = PastPosition;
+
+    fn next(&mut self) -> Option<PastPosition> {
+        let curr = self.game.node(self.node?);
+        let (to_play, player) = match (curr.parent, curr.color) {
+            (None, _) => {
+                // the root may say who moves first, as in to_play
+                let player = curr.setup.as_ref().and_then(|setup| setup.player);
+                (player.unwrap_or_else(|| self.game.first_player()), None)
+            }
+            (Some(_), BLACK) => (WHITE, Some(BLACK)),
+            (Some(_), _) => (BLACK, Some(WHITE)),
+        };
+        self.node = curr.parent;
+        Some(PastPosition {
+            hash: curr.hash,
+            to_play,
+            player: if curr.is_pass() || curr.is_setup() { None } else { player },
+        })
     }
 }
 
 pub struct Game {
-    pub root: Arc<Mutex<Node>>,
-    pub curr: Arc<Mutex<Node>>,
-    pub states: Vec<(
-        Vec<Vec<Intersection>>,
-        Arc<Mutex<Node>>,
-        Arc<Mutex<Node>>,
-        Zobrist,
-    )>,
+    pub rows: usize,
+    pub cols: usize,
+    pub info: GameInfo,
+    pub handicap: usize,
+    pub free_handicap: usize,
+    /// nodes of the game tree and the clipboard, indexed by id, where removed nodes leave an
+    /// empty slot so that ids are never reused
+    nodes: Vec<Option<Node>>,
+    pub root: NodeId,
+    pub curr: NodeId,
+    /// saved states, as the node and the hash when they were saved
+    pub states: Vec<(NodeId, Zobrist)>,
+    /// subtree cut from the game tree, waiting to be pasted
+    pub clipboard: Option<NodeId>,
 }
 
 impl Game {
-    pub fn new() -> Game {
-        let board = vec![vec![Intersection::Empty; COLS]; ROWS];
-        let root = Arc::new(Mutex::new(Node::new(board, (0, 0), BLACK, None)));
-        let curr = Arc::clone(&root);
+    pub fn new(rows: usize, cols: usize) -> Game {
+        let board = empty_board(rows, cols);
+        let hash = Zobrist::new(rows, cols).get_hash();
+        let mut root = Node::new(hash, None, BLACK, (0, 0), Vec::new(), None);
+        root.checkpoint = Some(board);
         Game {
-            root,
-            curr,
+            rows,
+            cols,
+            info: GameInfo::default(),
+            handicap: 0,
+            free_handicap: 0,
+            nodes: vec![Some(root)],
+            root: 0,
+            curr: 0,
             states: Vec::new(),
+            clipboard: None,
         }
     }
 
-    /// Add a node to the game tree
-    pub fn add_node(&mut self, board: Vec<Vec<Intersection>>, piece: (usize, usize), color: usize) {
-        let node = Arc::new(Mutex::new(Node::new(
-            board,
+    /// Get a node of the game tree or the clipboard
+    pub fn node(&self, id: NodeId) -> &Node {
+        self.nodes[id].as_ref().expect("node was removed from the game tree")
+    }
+
+    /// Get a node of the game tree or the clipboard to change it
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.nodes[id].as_mut().expect("node was removed from the game tree")
+    }
+
+    /// Get the current node
+    pub fn curr_node(&self) -> &Node {
+        self.node(self.curr)
+    }
+
+    /// Get the current node to change it
+    pub fn curr_node_mut(&mut self) -> &mut Node {
+        self.node_mut(self.curr)
+    }
+
+    /// Rebuild the board at a node, from the nearest node above that keeps the whole board
+    pub fn get_board(&self, id: NodeId) -> Vec<Vec<Intersection>> {
+        let mut path = Vec::new();
+        let mut id = id;
+        let mut board = loop {
+            let node = self.node(id);
+            if let Some(board) = &node.checkpoint {
+                break board.clone();
+            }
+            path.push(id);
+            id = node.parent.expect("the root keeps the whole board");
+        };
+        for &id in path.iter().rev() {
+            self.node(id).apply(&mut board);
+        }
+        board
+    }
+
+    /// Add a node to the game tree, with the prisoners taken by (black, white) in its move and
+    /// the stones it removed from the board
+    pub fn add_node(
+        &mut self,
+        board: &[Vec<Intersection>],
+        hash: u64,
+        piece: Option<(usize, usize)>,
+        color: usize,
+        captured: (usize, usize),
+        removed: Vec<(usize, usize)>,
+    ) {
+        let (black, white) = self.get_captures();
+        let mut node = Node::new(
+            hash,
             piece,
             color,
-            Some(Arc::clone(&self.curr)),
-        )));
+            (black + captured.0, white + captured.1),
+            removed,
+            Some(self.curr),
+        );
+        node.distance = self.curr_node().distance + 1;
+        if node.distance >= CHECKPOINT_INTERVAL {
+            node.checkpoint = Some(board.to_vec());
+            node.distance = 0;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Some(node));
+
+        // redo follows the line played most recently
+        let parent = self.curr_node_mut();
+        parent.children.push(id);
+        parent.main_line = parent.children.len() - 1;
+        self.curr = id;
+    }
+
+    /// Add a setup node to the game tree, which changes the board without a move. The player to
+    /// move stays the same unless the setup gives one
+    pub fn add_setup(&mut self, board: &[Vec<Intersection>], hash: u64, setup: Setup) {
+        let color = match setup.player.unwrap_or(self.to_play()) {
+            BLACK => WHITE,
+            _ => BLACK,
+        };
+        self.add_node(board, hash, None, color, (0, 0), Vec::new());
+        self.curr_node_mut().setup = Some(setup);
+    }
+
+    /// Replace the board and setup of the current node, which should be a setup node without
+    /// children
+    pub fn set_setup(&mut self, new_board: &[Vec<Intersection>], new_hash: u64, new_setup: Setup) {
+        let curr = self.curr_node_mut();
+        if let Some(player) = new_setup.player {
+            curr.color = if player == BLACK { WHITE } else { BLACK };
+        }
+        if curr.checkpoint.is_some() {
+            curr.checkpoint = Some(new_board.to_vec());
+        }
+        curr.hash = new_hash;
+        curr.setup = Some(new_setup);
+    }
+
+    /// Remove the current node, which should have no children, and move to its parent
+    pub fn pop_node(&mut self) {
+        let parent = match self.curr_node().parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        let curr = self.curr;
+        self.remove_child(parent, curr);
+        self.states.retain(|&(node, _)| node != curr);
+        self.nodes[curr] = None;
+        self.curr = parent;
+    }
+
+    /// List the children of the current node
+    pub fn get_variations(&self) -> Vec<Variation> {
+        let curr = self.curr_node();
+        curr.children
+            .iter()
+            .enumerate()
+            .map(|(i, &child)| {
+                let child = self.node(child);
+                Variation {
+                    piece: child.piece,
+                    color: child.color,
+                    setup: child.is_setup(),
+                    main_line: i == curr.main_line,
+                }
+            })
+            .collect()
+    }
+
+    /// Lay out the whole game tree, listing the nodes in preorder
+    pub fn get_tree(&self) -> TreeView {
+        let mut nodes: Vec<TreeNode> = Vec::new();
+        let mut rows = 0;
+
+        // walk the tree, with later children pushed first so that the first child comes next,
+        // keeping the position of the parent in the list and the index among its siblings
+        let mut stack: Vec<(NodeId, Option<usize>, usize)> = vec![(self.root, None, 0)];
+        while let Some((id, parent, index)) = stack.pop() {
+            let position = nodes.len();
+            let node = self.node(id);
+            let setup = node.is_setup();
+            let (parent_id, move_number, depth, path) = match parent {
+                Some(parent) => {
+                    let parent: &mut TreeNode = &mut nodes[parent];
+                    parent.children.push(id);
+                    let move_number = if setup {
+                        parent.move_number
+                    } else {
+                        parent.move_number + 1
+                    };
+                    let path = format!("{}.{}", parent.path, index);
+                    (Some(parent.id), move_number, parent.depth + 1, path)
+                }
+                None => (None, 0, 0, String::from("0")),
+            };
+            for (i, &child) in node.children.iter().enumerate().rev() {
+                stack.push((child, Some(position), i));
+            }
+            // a later child starts a new row below every row used so far
+            let row = match (parent, index) {
+                (Some(parent), 0) => nodes[parent].row,
+                (None, _) => 0,
+                _ => {
+                    rows += 1;
+                    rows
+                }
+            };
+            nodes.push(TreeNode {
+                id,
+                path,
+                parent: parent_id,
+                children: Vec::new(),
+                move_number,
+                color: node.color,
+                piece: node.piece,
+                setup,
+                has_comment: node.annotation.comment.is_some(),
+                depth,
+                row,
+            });
+        }
+        TreeView {
+            nodes,
+            current: self.curr,
+        }
+    }
+
+    /// List the nodes of the game tree in preorder, with the first child of each node first
+    pub fn preorder(&self) -> Vec<NodeId> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            stack.extend(self.node(id).children.iter().rev());
+            nodes.push(id);
+        }
+        nodes
+    }
+
+    /// Find the node with an id, if it is in the game tree
+    pub fn find_node(&self, id: NodeId) -> Option<NodeId> {
+        match self.nodes.get(id) {
+            Some(Some(_)) if self.is_descendant(id, self.root) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Check if a node is in the subtree below another node, including the node itself
+    fn is_descendant(&self, id: NodeId, ancestor: NodeId) -> bool {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            if id == ancestor {
+                return true;
+            }
+            node = self.nodes.get(id).and_then(|node| node.as_ref()?.parent);
+        }
+        false
+    }
+
+    /// Get the path of a node, which is 0 for the root followed by the index of the child taken
+    /// at each step down from the root, such as 0.3.1.0
+    pub fn get_path(&self, id: NodeId) -> String {
+        let mut indices = Vec::new();
+        let mut id = id;
+        while let Some(parent) = self.node(id).parent {
+            let index = self
+                .node(parent)
+                .children
+                .iter()
+                .position(|&child| child == id)
+                .unwrap_or(0);
+            indices.push(index.to_string());
+            id = parent;
+        }
+        indices.push(String::from("0"));
+        indices.reverse();
+        indices.join(".")
+    }
+
+    /// Find the node at a path from get_path
+    pub fn find_path(&self, path: &str) -> Option<NodeId> {
+        let mut indices = path.split('.');
+        if indices.next()? != "0" {
+            return None;
+        }
+        let mut id = self.root;
+        for index in indices {
+            let index: usize = index.parse().ok()?;
+            id = *self.node(id).children.get(index)?;
+        }
+        Some(id)
+    }
+
+    /// Get the child of a node followed when redoing, if it has any children
+    fn main_line_child(&self, id: NodeId) -> Option<NodeId> {
+        let node = self.node(id);
+        node.children.get(node.main_line).copied()
+    }
+
+    /// Find the node of a move on the current line, which runs from the root through the
+    /// current node and then follows the main line, or the last node if the line is shorter
+    pub fn find_move(&self, move_number: usize) -> NodeId {
+        // collect the nodes from the root to the current node
+        let mut path = vec![self.curr];
+        while let Some(parent) = self.node(path[path.len() - 1]).parent {
+            path.push(parent);
+        }
+        path.reverse();
+
+        // then walk down the line counting moves and passes, but not setup nodes
+        let mut count = 0;
+        let mut id = path[0];
+        let mut i = 1;
+        while count < move_number {
+            let next = match path.get(i) {
+                Some(&next) => next,
+                None => match self.main_line_child(id) {
+                    Some(next) => next,
+                    None => break,
+                },
+            };
+            if !self.node(next).is_setup() {
+                count += 1;
+            }
+            id = next;
+            i += 1;
+        }
+        id
+    }
+
+    /// Find the last node of the main line from the current node
+    pub fn find_end(&self) -> NodeId {
+        let mut id = self.curr;
+        while let Some(child) = self.main_line_child(id) {
+            id = child;
+        }
+        id
+    }
+
+    /// Find the nearest node before the current node with more than one child, or the root
+    pub fn find_previous_branch(&self) -> NodeId {
+        let mut id = self.curr;
+        while let Some(parent) = self.node(id).parent {
+            id = parent;
+            if self.node(id).children.len() > 1 {
+                break;
+            }
+        }
+        id
+    }
+
+    /// Find the nearest node after the current node on the main line with more than one child,
+    /// or the end of the main line
+    pub fn find_next_branch(&self) -> NodeId {
+        let mut id = self.curr;
+        while let Some(child) = self.main_line_child(id) {
+            id = child;
+            if self.node(id).children.len() > 1 {
+                break;
+            }
+        }
+        id
+    }
+
+    /// Take the subtree at the current node out of the game tree and move to its parent,
+    /// returning the subtree, or None at the root. Saved states inside the subtree are removed
+    fn detach(&mut self) -> Option<NodeId> {
+        let subtree = self.curr;
+        let parent = self.curr_node().parent?;
+        self.remove_child(parent, subtree)?;
+        let states = std::mem::take(&mut self.states);
+        self.states = states
+            .into_iter()
+            .filter(|&(node, _)| !self.is_descendant(node, subtree))
+            .collect();
+        // the subtree no longer hangs from the game tree, so find_node cannot reach it
+        self.node_mut(subtree).parent = None;
+        self.curr = parent;
+        Some(subtree)
+    }
+
+    /// Remove a child from the children of a node, keeping the main line on the same child, or
+    /// on the one before if the main line was the last child. Return its index
+    fn remove_child(&mut self, parent: NodeId, child: NodeId) -> Option<usize> {
+        let parent_node = self.node_mut(parent);
+        let index = parent_node.children.iter().position(|&id| id == child)?;
+        parent_node.children.remove(index);
+        let main_line = &mut parent_node.main_line;
+        if *main_line > index
+            || (*main_line == index && index == parent_node.children.len() && index > 0)
+        {
+            *main_line -= 1;
+        }
+        Some(index)
+    }
+
+    /// Remove a node and its descendants from the arena
+    fn free(&mut self, id: NodeId) {
+        if let Some(node) = self.nodes[id].take() {
+            for child in node.children {
+                self.free(child);
+            }
+        }
+    }
+
+    /// Delete the subtree at the current node and move to its parent. Return false at the root
+    pub fn delete_subtree(&mut self) -> bool {
+        match self.detach() {
+            Some(subtree) => {
+                self.free(subtree);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make the line through the current node the main line, by moving it to the front of the
+    /// children at every branch above it
+    pub fn promote_variation(&mut self) {
+        let mut id = self.curr;
+        while let Some(parent) = self.node(id).parent {
+            let parent_node = self.node_mut(parent);
+            if let Some(index) = parent_node.children.iter().position(|&child| child == id) {
+                let child = parent_node.children.remove(index);
+                parent_node.children.insert(0, child);
+                parent_node.main_line = 0;
+            }
+            id = parent;
+        }
+    }
+
+    /// Move the variation containing the current node one place earlier or later among its
+    /// siblings, at the nearest branch above the current node. Return false if it cannot move
+    pub fn move_variation(&mut self, earlier: bool) -> bool {
+        // find the first node of the variation, whose parent has more than one child
+        let mut id = self.curr;
+        let parent = loop {
+            let parent = match self.node(id).parent {
+                Some(parent) => parent,
+                None => return false,
+            };
+            if self.node(parent).children.len() > 1 {
+                break parent;
+            }
+            id = parent;
+        };
+
+        // swap it with its sibling, keeping the same child on the main line
+        let parent = self.node_mut(parent);
+        let index = match parent.children.iter().position(|&child| child == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let other = match (earlier, index) {
+            (true, 0) => return false,
+            (true, _) => index - 1,
+            (false, _) if index + 1 >= parent.children.len() => return false,
+            (false, _) => index + 1,
+        };
+        parent.children.swap(index, other);
+        if parent.main_line == index {
+            parent.main_line = other;
+        } else if parent.main_line == other {
+            parent.main_line = index;
+        }
+        true
+    }
+
+    /// Cut the subtree at the current node to the clipboard, and move to its parent. Return
+    /// false at the root
+    pub fn cut(&mut self) -> bool {
+        match self.detach() {
+            Some(subtree) => {
+                if let Some(old) = self.clipboard.replace(subtree) {
+                    self.free(old);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Paste the subtree on the clipboard as a new child of the current node, replaying its
+    /// moves and setup on the board here. A move onto an occupied intersection is dropped along
+    /// with the nodes after it. Return false if the clipboard is empty or nothing could be pasted
+    pub fn paste(&mut self, hash: &mut Zobrist) -> bool {
+        let subtree = match self.clipboard {
+            Some(subtree) => subtree,
+            None => return false,
+        };
+        let start = self.curr;
+        let pasted = self.replay(subtree, hash);
+        self.curr = start;
+        pasted
+    }
+
+    /// Add a copy of a node and its descendants below the current node, replaying each of them
+    /// on the board of its new parent
+    fn replay(&mut self, id: NodeId, hash: &mut Zobrist) -> bool {
+        let node = self.node(id).clone();
+
+        // add the node on the new board
+        let mut board = self.get_board(self.curr);
+        match (node.piece, node.is_setup()) {
+            (Some((x, y)), _) => {
+                if board[x][y] != Intersection::Empty {
+                    return false;
+                }
+                let (captured, suicide) = play_move(x, y, node.color, &mut board);
+                let new_hash = hash.update(&board);
+                let counts = if node.color == BLACK {
+                    (captured.len(), suicide.len())
+                } else {
+                    (suicide.len(), captured.len())
+                };
+                let removed = captured.into_iter().chain(suicide).collect();
+                self.add_node(&board, new_hash, node.piece, node.color, counts, removed);
+            }
+            (None, true) => {
+                let setup = node.get_setup();
+                set_stones(&setup.empty, EMPTY, &mut board);
+                set_stones(&setup.black, BLACK, &mut board);
+                set_stones(&setup.white, WHITE, &mut board);
+                let new_hash = hash.update(&board);
+                self.add_setup(&board, new_hash, setup);
+            }
+            (None, false) => {
+                let curr_hash = self.curr_node().hash;
+                self.add_node(&board, curr_hash, None, node.color, (0, 0), Vec::new());
+            }
+        }
+        let copy = self.curr;
         {
-            let mut self_node = self.curr.lock().unwrap();
-            match &mut *self_node {
-                Node::Move { children, .. } => {
-                    children.push(Arc::clone(&node));
+            let copy = self.curr_node_mut();
+            copy.annotation = node.annotation;
+            copy.markup = node.markup;
+            copy.dead = node.dead;
+        }
+
+        // replay the children from the copy, keeping the main line on the same child
+        let mut pasted = 0;
+        for (i, &child) in node.children.iter().enumerate() {
+            self.curr = copy;
+            if self.replay(child, hash) {
+                if i == node.main_line {
+                    self.node_mut(copy).main_line = pasted;
                 }
-                _ => (),
+                pasted += 1;
             }
         }
-        self.curr = node;
+        if node.main_line >= node.children.len() || pasted == 0 {
+            self.node_mut(copy).main_line = 0;
+        }
+        true
+    }
+
+    /// Get the prisoners taken by (black, white) up to the current node
+    pub fn get_captures(&self) -> (usize, usize) {
+        self.curr_node().captures
+    }
+
+    /// Get the color of the player who moves first, which is white once handicap stones are placed
+    pub fn first_player(&self) -> usize {
+        if self.handicap > 0 && self.free_handicap == 0 {
+            WHITE
+        } else {
+            BLACK
+        }
+    }
+
+    /// Check if black is still placing free handicap stones before the first move
+    pub fn is_placing_handicap(&self) -> bool {
+        // once the game has moves, the root board is fixed under them
+        self.free_handicap > 0 && self.curr == self.root && self.node(self.root).children.is_empty()
+    }
+
+    /// Replace the board at the root node, for stones placed before the first move
+    pub fn set_root(&mut self, new_board: Vec<Vec<Intersection>>, new_hash: u64) {
+        let root = self.root;
+        let root = self.node_mut(root);
+        root.checkpoint = Some(new_board);
+        root.hash = new_hash;
+    }
+
+    /// Get the color of the player to move at the current node
+    pub fn to_play(&self) -> usize {
+        let curr = self.curr_node();
+        if let Some(player) = curr.get_setup().player {
+            return player;
+        }
+        if curr.parent.is_none() {
+            return self.first_player();
+        }
+        match curr.color {
+            BLACK => WHITE,
+            _ => BLACK,
+        }
+    }
+
+    /// Get the positions from the current node back to the start of the game, walking up the
+    /// game tree without collecting them
+    pub fn history(&self) -> History<'_> {
+        History {
+            game: self,
+            node: Some(self.curr),
+        }
+    }
+
+    /// Check if the game has ended with two consecutive passes at the current node
+    pub fn is_over(&self) -> bool {
+        let curr = self.curr_node();
+        if !curr.is_pass() {
+            return false;
+        }
+        match curr.parent {
+            Some(parent) => self.node(parent).is_pass(),
+            None => false,
+        }
     }
 
     /// Save the current state of the game
-    pub fn save_state(&mut self, board: Vec<Vec<Intersection>>, hash: Zobrist) {
-        self.states
-            .push((board, Arc::clone(&self.curr), Arc::clone(&self.root), hash));
+    pub fn save_state(&mut self, hash: Zobrist) {
+        self.states.push((self.curr, hash));
     }
 
     /// Add states from a Saved game to the current game
     pub fn add_states(&mut self, saved: Saved) {
-        // pre-fill states vector with number of saved states
-        for i in 0..saved.states.len() {
-            self.states.push((
-                saved.states[i].0.clone(),
-                Arc::clone(&self.curr),
-                Arc::clone(&self.root),
-                saved.states[i].1.clone(),
-            ));
-        }
-
-        // iterate through node tree, matching the board to each saved state board
-        fn traverse_node(
-            game: &mut Game,
-            node: &Arc<Mutex<Node>>,
-            states: &Vec<Vec<Vec<Intersection>>>,
-        ) {
-            let mut node_data = node.lock().unwrap();
-            match &mut *node_data {
-                Node::Move {
-                    board, children, ..
-                } => {
-                    // search in states for matching board
-                    for i in 0..states.len() {
-                        if board == &states[i] {
-                            game.states[i].1 = Arc::clone(node);
-                            break;
-                        }
-                    }
-                    for child in children {
-                        traverse_node(game, child, states);
-                    }
-                }
-                _ => (),
+        // give the nodes back their ids, unless the tree no longer matches them
+        let nodes = self.preorder();
+        let unique: HashSet<&usize> = saved.ids.iter().collect();
+        let in_range = saved.ids.iter().all(|&id| id <= MAX_NODE_ID);
+        if saved.ids.len() == nodes.len() && unique.len() == nodes.len() && in_range {
+            self.renumber(&nodes, &saved.ids);
+        }
+
+        // add the state of each saved node, rebuilding each hash so that it matches the hashes
+        // in the game tree
+        for id in saved.state_nodes {
+            if let Some(id) = self.find_node(id) {
+                let mut hash = Zobrist::new(self.rows, self.cols);
+                hash.update(&self.get_board(id));
+                self.states.push((id, hash));
             }
         }
 
-        let saved_states = saved
-            .states
-            .iter()
-            .map(|(board, _)| board.clone())
-            .collect();
-        let root_clone = Arc::clone(&self.root);
-        traverse_node(self, &root_clone, &saved_states);
+        // files saved before nodes had ids only have boards, so find the first node with each
+        for (board, _) in saved.states {
+            let board: Vec<Vec<Intersection>> = board
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|intersection| match intersection {
+                            SavedIntersection::Empty => Intersection::Empty,
+                            SavedIntersection::Black(_) => Intersection::Black,
+                            SavedIntersection::White(_) => Intersection::White,
+                        })
+                        .collect()
+                })
+                .collect();
+            let mut hash = Zobrist::new(self.rows, self.cols);
+            hash.update(&board);
+            let id = self
+                .preorder()
+                .into_iter()
+                .find(|&id| self.node(id).hash == hash.get_hash())
+                .unwrap_or(self.curr);
+            self.states.push((id, hash));
+        }
+    }
+
+    /// Move the nodes of the game tree, listed in preorder, to new ids
+    fn renumber(&mut self, nodes: &[NodeId], ids: &[NodeId]) {
+        let mut old_nodes = std::mem::take(&mut self.nodes);
+        let mut new_ids = vec![0; old_nodes.len()];
+        for (&old, &new) in nodes.iter().zip(ids) {
+            new_ids[old] = new;
+        }
+        let new_id = |id: NodeId| new_ids[id];
+        let size = ids.iter().max().map_or(0, |id| id + 1);
+        self.nodes = vec![None; size];
+        for (&old, &new) in nodes.iter().zip(ids) {
+            let mut node = old_nodes[old].take().expect("node was removed from the game tree");
+            node.parent = node.parent.map(new_id);
+            node.children = node.children.iter().map(|&child| new_id(child)).collect();
+            self.nodes[new] = Some(node);
+        }
+        self.root = new_id(self.root);
+        self.curr = new_id(self.curr);
+        self.states = Vec::new();
+        self.clipboard = None;
     }
 
     /// Convert the game tree to SGF string
     pub fn to_sgf(&self) -> String {
-        let mut sgf = String::from("(;FF[4]GM[1]SZ[19]");
-
-        fn traverse_node(node: &Arc<Mutex<Node>>, sgf: &mut String, mut first: usize) {
-            let node = node.lock().unwrap();
-            match &*node {
-                Node::Move {
-                    piece,
-                    color,
-                    children,
-                    ..
-                } => {
-                    // ignore first placeholder node
-                    if first < 1 {
-                        first += 1;
-                        if children.len() > 1 {
-                            for child in children {
-                                sgf.push('(');
-                                traverse_node(child, sgf, first);
-                                sgf.push(')');
+        let mut sgf = String::from("(;FF[4]GM[1]");
+        if self.rows == self.cols {
+            sgf.push_str(&format!("SZ[{}]", self.cols));
+        } else {
+            sgf.push_str(&format!("SZ[{}:{}]", self.cols, self.rows));
+        }
+        sgf.push_str(&self.info.to_sgf());
+        if self.handicap > 0 {
+            sgf.push_str(&format!("HA[{}]", self.handicap));
+        }
+
+        // write the stones placed before the first move as setup properties
+        let root_board = self.get_board(self.root);
+        let mut black = String::new();
+        let mut white = String::new();
+        for (i, row) in root_board.iter().enumerate() {
+            for (j, intersection) in row.iter().enumerate() {
+                match intersection {
+                    Intersection::Black => black.push_str(&format!("[{}]", sgf_point(Some((i, j))))),
+                    Intersection::White => white.push_str(&format!("[{}]", sgf_point(Some((i, j))))),
+                    Intersection::Empty => (),
+                }
+            }
+        }
+        if !black.is_empty() {
+            sgf.push_str(&format!("AB{}", black));
+        }
+        if !white.is_empty() {
+            sgf.push_str(&format!("AW{}", white));
+        }
+        let root = self.node(self.root);
+        sgf.push_str(&Setup { player: root.get_setup().player, ..Setup::default() }.to_sgf());
+        sgf.push_str(&root.annotation.to_sgf());
+        sgf.push_str(&markup_to_sgf(&root.markup));
+
+        fn traverse_children(game: &Game, id: NodeId, sgf: &mut String) {
+            let children = &game.node(id).children;
+            if children.len() > 1 {
+                for &child in children {
+                    sgf.push('(');
+                    traverse_node(game, child, sgf);
+                    sgf.push(')');
+                }
+            } else if children.len() == 1 {
+                traverse_node(game, children[0], sgf);
+            }
+        }
+
+        fn traverse_node(game: &Game, id: NodeId, sgf: &mut String) {
+            let node = game.node(id);
+
+            // write the stones of a setup node, or else the move color and location
+            match (&node.setup, node.piece) {
+                (Some(setup), None) => sgf.push_str(&format!(";{}", setup.to_sgf())),
+                _ => {
+                    let color_str = if node.color == BLACK { "B" } else { "W" };
+                    sgf.push_str(&format!(";{}[{}]", color_str, sgf_point(node.piece)));
+                }
+            }
+            sgf.push_str(&node.annotation.to_sgf());
+            sgf.push_str(&markup_to_sgf(&node.markup));
+
+            // mark the territory of a scored position, including dead stones
+            if !node.dead.is_empty() {
+                let owners = get_territory(&game.get_board(id), &node.dead);
+                for (prop, owner) in [("TB", BLACK), ("TW", WHITE)] {
+                    let mut points = String::new();
+                    for (i, row) in owners.iter().enumerate() {
+                        for (j, &point_owner) in row.iter().enumerate() {
+                            if point_owner == owner {
+                                points.push_str(&format!("[{}]", sgf_point(Some((i, j)))));
                             }
-                        } else if children.len() == 1 {
-                            traverse_node(&children[0], sgf, first);
                         }
-                        return;
                     }
-
-                    // get move color and location
-                    let color_str = if *color == BLACK { "B" } else { "W" };
-                    let x = (piece.1 as u8 + b'a') as char;
-                    let y = (piece.0 as u8 + b'a') as char;
-                    sgf.push_str(&format!(";{}[{}{}]", color_str, x, y));
-
-                    // go to next node
-                    if children.len() > 1 {
-                        for child in children {
-                            sgf.push('(');
-                            traverse_node(child, sgf, first);
-                            sgf.push(')');
-                        }
-                    } else if children.len() == 1 {
-                        traverse_node(&children[0], sgf, first);
-                    } else {
-                        sgf.push(';');
+                    if !points.is_empty() {
+                        sgf.push_str(&format!("{}{}", prop, points));
                     }
                 }
-                Node::End { piece, color, .. } => {
-                    println!("Node::End");
-                    let color_str = if *color == BLACK { "B" } else { "W" };
-                    let x = (piece.1 as u8 + b'a') as char;
-                    let y = (piece.0 as u8 + b'a') as char;
-                    sgf.push_str(&format!(";{}[{}{}]", color_str, x, y));
-                }
             }
+
+            // go to next node
+            traverse_children(game, id, sgf);
         }
 
-        traverse_node(&self.root, &mut sgf, 0);
+        traverse_children(self, self.root, &mut sgf);
         sgf.push(')');
         sgf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Play a move at the current node for the player to move, returning the new node
+    fn play(game: &mut Game, x: usize, y: usize) -> NodeId {
+        let mut board = game.get_board(game.curr);
+        let color = game.to_play();
+        let (captured, suicide) = play_move(x, y, color, &mut board);
+        let mut hash = Zobrist::new(game.rows, game.cols);
+        let new_hash = hash.update(&board);
+        let removed = captured.into_iter().chain(suicide).collect();
+        game.add_node(&board, new_hash, Some((x, y)), color, (0, 0), removed);
+        game.curr
+    }
+
+    /// Play moves at several points from the same node, returning the new children in order
+    fn branch(game: &mut Game, from: NodeId, points: &[(usize, usize)]) -> Vec<NodeId> {
+        points
+            .iter()
+            .map(|&(x, y)| {
+                game.curr = from;
+                play(game, x, y)
+            })
+            .collect()
+    }
+
+    /// Check that every main line is one of the children, and every saved state is in the tree
+    fn assert_consistent(game: &Game) {
+        for id in game.preorder() {
+            let node = game.node(id);
+            assert!(node.children.is_empty() || node.main_line < node.children.len());
+        }
+        for &(id, _) in game.states.iter() {
+            assert_eq!(game.find_node(id), Some(id));
+        }
+    }
+
+    fn save(game: &mut Game, id: NodeId) {
+        game.curr = id;
+        game.save_state(Zobrist::new(game.rows, game.cols));
+    }
+
+    #[test]
+    fn deleting_a_variation_keeps_the_main_line() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let children = branch(&mut game, root, &[(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(game.node(root).main_line, 2);
+        save(&mut game, children[0]);
+        save(&mut game, children[1]);
+
+        game.curr = children[0];
+        assert!(game.delete_subtree());
+        assert_eq!(game.curr, root);
+        assert_eq!(game.node(root).children, vec![children[1], children[2]]);
+        assert_eq!(game.node(root).main_line, 1);
+        assert_eq!(game.states.len(), 1);
+        assert_eq!(game.find_node(children[0]), None);
+        assert_consistent(&game);
+
+        // deleting the last child on the main line moves it to the one before
+        game.curr = children[2];
+        assert!(game.delete_subtree());
+        assert_eq!(game.node(root).main_line, 0);
+        assert_consistent(&game);
+
+        game.curr = root;
+        assert!(!game.delete_subtree());
+    }
+
+    #[test]
+    fn removing_a_setup_node_keeps_the_main_line_and_states() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        branch(&mut game, root, &[(0, 0)]);
+        game.curr = root;
+        let board = game.get_board(root);
+        let setup = Setup {
+            black: vec![(4, 4)],
+            ..Setup::default()
+        };
+        game.add_setup(&board, 0, setup);
+        let setup_node = game.curr;
+        save(&mut game, setup_node);
+        assert_eq!(game.node(root).main_line, 1);
+
+        game.pop_node();
+        assert_eq!(game.curr, root);
+        assert_eq!(game.node(root).main_line, 0);
+        assert!(game.states.is_empty());
+        assert_consistent(&game);
+    }
+
+    #[test]
+    fn cutting_removes_the_subtree_from_the_tree() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let first = play(&mut game, 0, 0);
+        let replies = branch(&mut game, first, &[(1, 1), (2, 2)]);
+        save(&mut game, replies[1]);
+        save(&mut game, root);
+
+        game.curr = first;
+        assert!(game.cut());
+        assert_eq!(game.curr, root);
+        assert!(game.node(root).children.is_empty());
+        assert_eq!(game.clipboard, Some(first));
+        assert_eq!(game.find_node(first), None);
+        assert_eq!(game.find_node(replies[1]), None);
+        assert_eq!(game.states.len(), 1);
+        assert_consistent(&game);
+    }
+
+    #[test]
+    fn pasting_replays_the_subtree_with_its_main_line() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let first = play(&mut game, 0, 0);
+        let replies = branch(&mut game, first, &[(1, 1), (2, 2)]);
+        game.curr = replies[1];
+        game.curr_node_mut().set_annotation(Annotation {
+            comment: Some(String::from("reply")),
+            ..Annotation::default()
+        });
+        game.curr = first;
+        assert!(game.cut());
+
+        // paste twice below the root, which keeps the clipboard
+        let mut hash = Zobrist::new(9, 9);
+        assert!(game.paste(&mut hash));
+        assert!(game.paste(&mut hash));
+        assert_eq!(game.curr, root);
+        assert_eq!(game.node(root).children.len(), 2);
+        assert_eq!(game.node(root).main_line, 1);
+        for &copy in game.node(root).children.iter() {
+            let node = game.node(copy);
+            assert_eq!(node.get_piece(), Some((0, 0)));
+            assert_eq!(node.children.len(), 2);
+            assert_eq!(node.main_line, 1);
+            let reply = game.node(node.children[1]);
+            assert_eq!(reply.get_piece(), Some((2, 2)));
+            assert_eq!(reply.get_annotation().comment.as_deref(), Some("reply"));
+            assert_eq!(game.get_board(node.children[1])[2][2], Intersection::White);
+        }
+        assert!(game.clipboard.is_some());
+        assert_consistent(&game);
+
+        // a move onto an occupied intersection cannot be pasted
+        game.curr = game.node(root).children[0];
+        assert!(!game.paste(&mut hash));
+        assert_consistent(&game);
+    }
+
+    #[test]
+    fn moving_a_variation_keeps_the_same_main_line_child() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let children = branch(&mut game, root, &[(0, 0), (1, 1), (2, 2)]);
+        let deep = play(&mut game, 5, 5);
+        let main = children[2];
+
+        game.curr = children[0];
+        assert!(game.move_variation(false));
+        assert_eq!(game.node(root).children, vec![children[1], children[0], main]);
+        assert_eq!(game.node(root).children[game.node(root).main_line], main);
+
+        // a node deep in a variation moves the whole variation
+        game.curr = deep;
+        assert!(game.move_variation(true));
+        assert_eq!(game.node(root).children, vec![children[1], main, children[0]]);
+        assert_eq!(game.node(root).main_line, 1);
+        assert!(game.move_variation(true));
+        assert!(!game.move_variation(true));
+        assert_eq!(game.node(root).children[game.node(root).main_line], main);
+
+        game.curr = children[0];
+        assert!(!game.move_variation(false));
+        assert_consistent(&game);
+    }
+
+    #[test]
+    fn promoting_a_variation_makes_it_the_main_line_at_every_branch() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let children = branch(&mut game, root, &[(0, 0), (1, 1)]);
+        let replies = branch(&mut game, children[1], &[(2, 2), (3, 3)]);
+        game.node_mut(children[1]).main_line = 0;
+        game.node_mut(root).main_line = 0;
+
+        game.curr = replies[1];
+        game.promote_variation();
+        assert_eq!(game.node(root).children, vec![children[1], children[0]]);
+        assert_eq!(game.node(root).main_line, 0);
+        assert_eq!(game.node(children[1]).children, vec![replies[1], replies[0]]);
+        assert_eq!(game.node(children[1]).main_line, 0);
+        assert_eq!(game.get_path(replies[1]), "0.0.0");
+        assert_consistent(&game);
+    }
+
+    #[test]
+    fn saved_ids_and_states_survive_rebuilding_the_tree() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let children = branch(&mut game, root, &[(0, 0), (1, 1), (2, 2)]);
+        game.curr = children[1];
+        assert!(game.delete_subtree());
+        game.curr = children[2];
+        let end = play(&mut game, 3, 3);
+        save(&mut game, end);
+        save(&mut game, children[0]);
+        let saved = Saved::new(&game);
+        assert_eq!(saved.ids, vec![root, children[0], children[2], end]);
+        assert_eq!(saved.state_nodes, vec![end, children[0]]);
+
+        // the same tree built again has ids without the gap of the deleted node
+        let mut loaded = Game::new(9, 9);
+        branch(&mut loaded, root, &[(0, 0), (2, 2)]);
+        play(&mut loaded, 3, 3);
+        assert_ne!(loaded.preorder(), saved.ids);
+        loaded.curr = loaded.root;
+
+        let json = serde_json::to_string(&saved).unwrap();
+        loaded.add_states(serde_json::from_str(&json).unwrap());
+        assert_eq!(loaded.preorder(), saved.ids);
+        let states: Vec<NodeId> = loaded.states.iter().map(|&(id, _)| id).collect();
+        assert_eq!(states, saved.state_nodes);
+        assert_eq!(loaded.get_path(end), game.get_path(end));
+        assert_eq!(loaded.get_board(end), game.get_board(end));
+        assert_consistent(&loaded);
+    }
+
+    #[test]
+    fn saved_ids_out_of_range_are_ignored() {
+        let mut game = Game::new(9, 9);
+        let root = game.root;
+        let first = play(&mut game, 0, 0);
+        save(&mut game, first);
+        let mut saved = Saved::new(&game);
+        saved.ids[1] = usize::MAX / 2;
+
+        let mut loaded = Game::new(9, 9);
+        play(&mut loaded, 0, 0);
+        loaded.add_states(saved);
+        assert_eq!(loaded.preorder(), vec![root, first]);
+        assert_eq!(loaded.states.len(), 1);
+    }
+
+    #[test]
+    fn history_follows_the_player_given_at_the_root() {
+        let mut game = Game::new(9, 9);
+        let board = game.get_board(game.root);
+        let hash = game.curr_node().hash;
+        game.set_setup(&board, hash, Setup { player: Some(WHITE), ..Setup::default() });
+        assert_eq!(game.to_play(), WHITE);
+        assert_eq!(game.history().next().map(|past| past.to_play), Some(WHITE));
+
+        // moves after the root alternate from there
+        game.add_node(&board, hash, None, WHITE, (0, 0), Vec::new());
+        let to_play: Vec<usize> = game.history().map(|past| past.to_play).collect();
+        assert_eq!(to_play, vec![BLACK, WHITE]);
+    }
+}
@@ -1,21 +1,47 @@
+use std::fmt;
 use std::sync::Mutex;
-use std::collections::HashSet;
+use std::ops::{BitAnd, BitOr, Not};
 use serde::{Serialize, Deserialize};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use crate::game::Game;
+use crate::rules::Ruleset;
 
-pub const ROWS: usize = 19;
-pub const COLS: usize = 19;
+pub const DEFAULT_SIZE: usize = 19;
+pub const MAX_SIZE: usize = 52;
 const EMPTY: usize = 0;
 const BLACK: usize = 1;
 const WHITE: usize = 2;
+const ZOBRIST_SEED: u64 = 0x676f_6261_7365;
+/// Number of words in a bitboard, enough for the largest board with a spare column
+const WORDS: usize = (MAX_SIZE * (MAX_SIZE + 1) + 63) / 64;
 
 /// Enum for the intersection of a Go board, that can be either empty, black, or white
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Intersection {
     Empty,
-    Black(Group),
-    White(Group),
+    Black,
+    White,
+}
+
+impl Intersection {
+    /// Get the intersection holding a stone of a color, or an empty one
+    pub fn stone(color: usize) -> Intersection {
+        match color {
+            BLACK => Intersection::Black,
+            WHITE => Intersection::White,
+            _ => Intersection::Empty,
+        }
+    }
+
+    /// Get the color of the stone on the intersection
+    pub fn color(&self) -> usize {
+        match self {
+            Intersection::Empty => EMPTY,
+            Intersection::Black => BLACK,
+            Intersection::White => WHITE,
+        }
+    }
 }
 
 /// Tauri wrapper struct for the game tree
@@ -28,410 +54,795 @@ pub struct Board {
     pub pieces: Mutex<Vec<Vec<Intersection>>>,
 }
 
-/// The group of connected stones on the board, their intersections and their liberties
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub struct Group {
-    pub intersections: HashSet<(usize, usize)>,
-    pub liberties: HashSet<(usize, usize)>,
-}
-
 /// Tauri wrapper struct for the Zobrist hash
 pub struct Hash {
     pub zobrist: Mutex<Zobrist>,
 }
 
+/// Rule deciding which repeated positions make a move illegal
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KoRule {
+    /// Only the immediate recapture of a ko is forbidden
+    Simple,
+    /// A move may not recreate any earlier board position
+    Positional,
+    /// A move may not recreate an earlier board position with the same player to move
+    Situational,
+    /// A move may not recreate a board position that the same player created with a move
+    NaturalSituational,
+}
+
+/// A position reached earlier in the game, used to detect repetition
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PastPosition {
+    /// hash of the board, without the player to move
+    pub hash: u64,
+    /// the player to move in this position
+    pub to_play: usize,
+    /// the player whose move created this position, or None for the start or a pass
+    pub player: Option<usize>,
+}
+
 /// Zobrist hashing for Go board to detect ko
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Zobrist {
-    positions: [[[u64; 3]; COLS]; ROWS],
+    positions: Vec<Vec<[u64; 3]>>,
+    #[serde(default)]
+    to_move: u64,
     board: Vec<Vec<Intersection>>,
     hash: u64,
-    prev_hashes: HashSet<u64>,
 }
 
 impl Zobrist {
-    pub fn new() -> Zobrist {
-        // initialize keys for positions
-        let mut positions = [[[0; 3]; COLS]; ROWS];
-        let mut rng = rand::thread_rng();
-        for i in 0..ROWS {
-            for j in 0..COLS {
-                for k in 0..3 {
-                    positions[i][j][k] = rng.gen();
+    pub fn new(rows: usize, cols: usize) -> Zobrist {
+        // initialize keys for positions, from a fixed seed so that hashes stored in the game
+        // tree are still valid after the Zobrist is recreated
+        let mut positions = vec![vec![[0; 3]; cols]; rows];
+        let mut rng = StdRng::seed_from_u64(ZOBRIST_SEED);
+        for row in positions.iter_mut() {
+            for keys in row.iter_mut() {
+                for key in keys.iter_mut() {
+                    *key = rng.gen();
                 }
             }
         }
+        let to_move: u64 = rng.gen();
 
         // calculate initial hash
         let mut hash: u64 = 0;
-        let mut prev_hashes: HashSet<u64> = HashSet::new();
-        for i in 0..ROWS {
-            for j in 0..COLS {
-                hash ^= positions[i][j][EMPTY];
+        for row in positions.iter() {
+            for keys in row.iter() {
+                hash ^= keys[EMPTY];
             }
         }
-        prev_hashes.insert(hash);
-        let board: Vec<Vec<Intersection>> = vec![vec![Intersection::Empty; COLS]; ROWS];
+        let board: Vec<Vec<Intersection>> = empty_board(rows, cols);
 
         Zobrist {
             positions,
+            to_move,
             board,
             hash,
-            prev_hashes,
         }
     }
 
-    /// Update the hash with a new board state
-    pub fn update(&mut self, new_board: &Vec<Vec<Intersection>>) -> bool {
+    /// Get the hash of the current board
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Calculate the hash of a new board state from the differences with the current board
+    pub fn calculate(&self, new_board: &[Vec<Intersection>]) -> u64 {
+        let mut new_hash: u64 = self.hash;
+        for (i, row) in self.board.iter().enumerate() {
+            for (j, &intersection) in row.iter().enumerate() {
+                let new_intersection = new_board[i][j];
+                if intersection == new_intersection {
+                    continue;
+                }
+                let old_key = self.positions[i][j][intersection.color()];
+                let new_key = self.positions[i][j][new_intersection.color()];
+                new_hash ^= old_key ^ new_key;
+            }
+        }
+        new_hash
+    }
+
+    /// Calculate the hash of a set of stones from the differences with the current board
+    pub fn calculate_stones(&self, stones: &Stones) -> u64 {
         let mut new_hash: u64 = self.hash;
-        for i in 0..ROWS {
-            for j in 0..COLS {
-                if self.board[i][j] == new_board[i][j] {
+        for (i, row) in self.board.iter().enumerate() {
+            for (j, &intersection) in row.iter().enumerate() {
+                let new_intersection = stones.get(i, j);
+                if intersection == new_intersection {
                     continue;
                 }
-                let old_color = match self.board[i][j] {
-                    Intersection::Empty => EMPTY,
-                    Intersection::Black(_) => BLACK,
-                    Intersection::White(_) => WHITE,
-                };
-                let new_color = match new_board[i][j] {
-                    Intersection::Empty => EMPTY,
-                    Intersection::Black(_) => BLACK,
-                    Intersection::White(_) => WHITE,
-                };
-                let old_key = self.positions[i][j][old_color];
-                let new_key = self.positions[i][j][new_color];
+                let old_key = self.positions[i][j][intersection.color()];
+                let new_key = self.positions[i][j][new_intersection.color()];
                 new_hash ^= old_key ^ new_key;
             }
         }
+        new_hash
+    }
+
+    /// Update the hash with a legal move, whose outcome already has the new hash, and return it
+    pub fn play(&mut self, outcome: &MoveOutcome) -> u64 {
+        outcome.apply(&mut self.board);
+        self.hash = outcome.hash();
+        self.hash
+    }
+
+    /// Update the hash with a new board state, and return the new hash
+    pub fn update(&mut self, new_board: &[Vec<Intersection>]) -> u64 {
+        self.hash = self.calculate(new_board);
+        self.board = new_board.to_vec();
+        self.hash
+    }
 
-        if self.prev_hashes.contains(&new_hash) {
-            false
+    /// Mix the player to move into a board hash
+    pub fn situation(&self, hash: u64, to_play: usize) -> u64 {
+        if to_play == WHITE {
+            hash ^ self.to_move
         } else {
-            self.hash = new_hash;
-            self.board = new_board.clone();
-            self.prev_hashes.insert(self.hash);
-            true
+            hash
         }
     }
-}
 
-/// Find if a intersection has any adjacent liberties and add them to the group
-fn find_liberties(x: usize, y: usize, board: &Vec<Vec<Intersection>>, libs: &mut HashSet<(usize, usize)>) {
-    let intersection = &board[x][y];
-    match intersection {
-        Intersection::Black(_) | Intersection::White(_) => {
-            if x > 0 && board[x - 1][y] == Intersection::Empty {
-                libs.insert((x - 1, y));
-            }
-            if x < ROWS - 1 && board[x + 1][y] == Intersection::Empty {
-                libs.insert((x + 1, y));
+    /// Check if a move by color that results in a board hash repeats a position under a ko rule,
+    /// given the positions from the current one back to the start of the game
+    pub fn is_repeat<H>(&self, rule: KoRule, new_hash: u64, color: usize, mut history: H) -> bool
+    where
+        H: Iterator<Item = PastPosition>,
+    {
+        match rule {
+            KoRule::Simple => {
+                // the position before the last move is the only one that may not be recreated
+                history.nth(1).map_or(false, |past| past.hash == new_hash)
             }
-            if y > 0 && board[x][y - 1] == Intersection::Empty {
-                libs.insert((x, y - 1));
+            KoRule::Positional => history.any(|past| past.hash == new_hash),
+            KoRule::Situational => {
+                let to_play = if color == BLACK { WHITE } else { BLACK };
+                let situation = self.situation(new_hash, to_play);
+                history.any(|past| self.situation(past.hash, past.to_play) == situation)
             }
-            if y < COLS - 1 && board[x][y + 1] == Intersection::Empty {
-                libs.insert((x, y + 1));
+            KoRule::NaturalSituational => {
+                history.any(|past| past.hash == new_hash && past.player == Some(color))
             }
         }
-        _ => {}
     }
 }
 
-// precondition: all intersections have been updated
-/// Get all the liberties for a group specified by an intersection on the board
-pub fn get_liberties(x: usize, y: usize, color: usize, board: &mut Vec<Vec<Intersection>>) {
-    // initialize clean group with no liberties
-    let mut move_group: Group = Group { intersections: HashSet::new(), liberties: HashSet::new() };
+/// Create an empty board with the given dimensions
+pub fn empty_board(rows: usize, cols: usize) -> Vec<Vec<Intersection>> {
+    vec![vec![Intersection::Empty; cols]; rows]
+}
+
+/// A set of intersections with one bit for each, stored row by row with a spare column between
+/// rows so that shifting bits sideways never wraps them onto the next row
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Bitboard([u64; WORDS]);
 
-    // check and get group from the intersection
-    if let Intersection::Black(ref mut group) = board[x][y] {
-        move_group.intersections = group.intersections.clone();
-    } else if let Intersection::White(ref mut group) = board[x][y] {
-        move_group.intersections = group.intersections.clone();
-    } else {
-        return;
+impl Default for Bitboard {
+    fn default() -> Bitboard {
+        Bitboard([0; WORDS])
     }
+}
 
-    // find liberties for all intersections in the group
-    for i in move_group.intersections.iter() {
-        find_liberties(i.0, i.1, board, &mut move_group.liberties);
+impl Bitboard {
+    /// Create an empty set
+    pub fn new() -> Bitboard {
+        Bitboard::default()
     }
 
-    // set move_group for all intersections in the group
-    for i in move_group.intersections.iter() {
-        if color == 1 {
-            if let Intersection::Black(ref mut group) = board[i.0][i.1] {
-                group.liberties = move_group.liberties.clone();
-            }
-        } else {
-            if let Intersection::White(ref mut group) = board[i.0][i.1] {
-                group.liberties = move_group.liberties.clone();
-            }
+    fn contains(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.0[i / 64] &= !(1 << (i % 64));
+    }
+
+    /// Check if the set has no intersections
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&word| word == 0)
+    }
+
+    /// Count the intersections in the set
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Move every bit n places up, for 0 < n < 64
+    fn shift_up(&self, n: usize) -> Bitboard {
+        let mut shifted = [0; WORDS];
+        shifted[0] = self.0[0] << n;
+        for (i, word) in shifted.iter_mut().enumerate().skip(1) {
+            *word = self.0[i] << n | self.0[i - 1] >> (64 - n);
         }
+        Bitboard(shifted)
     }
-}
 
-/// Given coordinates, use flood fill to find all coordinates in the group
-pub fn get_intersections(x: usize, y: usize, color: usize, board: &mut Vec<Vec<Intersection>>) {
-    // initialize group with empty intersections and no liberties
-    let mut group: Group = Group { intersections: HashSet::new(), liberties: HashSet::new() };
-
-    // use flood fill to find all intersections in the group
-    let mut visited = vec![vec![false; COLS]; ROWS];
-    let mut queue = vec![(x, y)];
-    while !queue.is_empty() {
-        // get current intersection from queue
-        let (x, y) = queue.pop().unwrap();
-        if visited[x][y] {
-            continue;
-        }
-        visited[x][y] = true;
-
-        // update intersections according to color
-        match board[x][y] {
-            Intersection::Black(_) => {
-                if color == 1 {
-                    group.intersections.insert((x, y));
-                } else {
-                    continue;
-                }
-            },
-            Intersection::White(_) => {
-                if color == 2 {
-                    group.intersections.insert((x, y));
-                } else {
-                    continue;
+    /// Move every bit n places down, for 0 < n < 64
+    fn shift_down(&self, n: usize) -> Bitboard {
+        let mut shifted = [0; WORDS];
+        for (i, word) in shifted.iter_mut().enumerate().take(WORDS - 1) {
+            *word = self.0[i] >> n | self.0[i + 1] << (64 - n);
+        }
+        shifted[WORDS - 1] = self.0[WORDS - 1] >> n;
+        Bitboard(shifted)
+    }
+
+    /// Iterate over the indices of the bits in the set
+    fn bits(self) -> impl Iterator<Item = usize> {
+        self.0.into_iter().enumerate().flat_map(|(i, mut word)| {
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
                 }
-            },
-            _ => continue,
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(mut self, other: Bitboard) -> Bitboard {
+        for (word, other) in self.0.iter_mut().zip(other.0) {
+            *word &= other;
         }
+        self
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
 
-        // update queue for flood fill
-        if x > 0 { queue.push((x - 1, y)); }
-        if x < ROWS - 1 { queue.push((x + 1, y)); }
-        if y > 0 { queue.push((x, y - 1)); }
-        if y < COLS - 1 { queue.push((x, y + 1)); }
+    fn bitor(mut self, other: Bitboard) -> Bitboard {
+        for (word, other) in self.0.iter_mut().zip(other.0) {
+            *word |= other;
+        }
+        self
     }
+}
 
-    // update for all intersections in the group
-    for i in group.intersections.iter() {
-        if color == 1 {
-            if let Intersection::Black(ref mut ref_group) = board[i.0][i.1] {
-                ref_group.intersections = group.intersections.clone();
-            }
-        } else {
-            if let Intersection::White(ref mut ref_group) = board[i.0][i.1] {
-                ref_group.intersections = group.intersections.clone();
-            }
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(mut self) -> Bitboard {
+        for word in self.0.iter_mut() {
+            *word = !*word;
         }
+        self
     }
 }
 
-/// Simulate the validation process
-pub fn simulate_val(x: usize, y: usize, color: usize, mut board: Vec<Vec<Intersection>>, mut hash: Zobrist) -> bool {
-    let intersection = &board[x][y];
-    let mut is_valid: bool;
-
-    // check for existing piece
-    match intersection {
-        Intersection::Empty => is_valid = true,
-        _ => is_valid = false,
-    }
-    if !is_valid { return is_valid; }
-
-    // prevent suicide by checking if group has any liberties
-    is_valid = false;
-    let mut check_liberty = |row: usize, col: usize| {
-        if board[row][col] == Intersection::Empty {
-            is_valid = true;
-        } else if matches!(board[row][col], Intersection::Black(_)) && color == 1 {
-            get_liberties(row, col, color, &mut board);
-            if let Intersection::Black(group) = &board[row][col] {
-                if group.liberties.len() > 1 {
-                    is_valid = true;
-                }
+/// A compact copy of the stones on a board, with a bitboard for each color, so that finding
+/// groups and liberties and placing stones never allocates
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Stones {
+    cols: usize,
+    /// every intersection on the board, leaving out the spare column
+    points: Bitboard,
+    black: Bitboard,
+    white: Bitboard,
+}
+
+impl Stones {
+    /// Create an empty board with the given dimensions
+    pub fn new(rows: usize, cols: usize) -> Stones {
+        let mut points = Bitboard::new();
+        for x in 0..rows {
+            for y in 0..cols {
+                points.insert(x * (cols + 1) + y);
             }
-        } else if matches!(board[row][col], Intersection::White(_)) && color == 2 {
-            get_liberties(row, col, color, &mut board);
-            if let Intersection::White(group) = &board[row][col] {
-                if group.liberties.len() > 1 {
-                    is_valid = true;
-                }
+        }
+        Stones {
+            cols,
+            points,
+            black: Bitboard::new(),
+            white: Bitboard::new(),
+        }
+    }
+
+    /// Copy the stones of a board
+    pub fn from_board(board: &[Vec<Intersection>]) -> Stones {
+        let mut stones = Stones::new(board.len(), board[0].len());
+        for (x, row) in board.iter().enumerate() {
+            for (y, &intersection) in row.iter().enumerate() {
+                stones.set(x, y, intersection);
             }
         }
-    };
-    if x > 0 {
-        check_liberty(x - 1, y);
+        stones
     }
-    if x < ROWS - 1 {
-        check_liberty(x + 1, y);
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        x * (self.cols + 1) + y
     }
-    if y > 0 {
-        check_liberty(x, y - 1);
+
+    /// Get an intersection of the board
+    pub fn get(&self, x: usize, y: usize) -> Intersection {
+        let i = self.index(x, y);
+        if self.black.contains(i) {
+            Intersection::Black
+        } else if self.white.contains(i) {
+            Intersection::White
+        } else {
+            Intersection::Empty
+        }
     }
-    if y < COLS - 1 {
-        check_liberty(x, y + 1);
+
+    /// Set an intersection of the board
+    pub fn set(&mut self, x: usize, y: usize, intersection: Intersection) {
+        let i = self.index(x, y);
+        self.black.remove(i);
+        self.white.remove(i);
+        match intersection {
+            Intersection::Black => self.black.insert(i),
+            Intersection::White => self.white.insert(i),
+            Intersection::Empty => (),
+        }
+    }
+
+    /// Get the empty intersections of the board
+    pub fn empty(&self) -> Bitboard {
+        self.points & !(self.black | self.white)
+    }
+
+    /// Get the intersections next to a set, not including the set itself
+    fn neighbors(&self, set: Bitboard) -> Bitboard {
+        let stride = self.cols + 1;
+        let spread = set.shift_up(1) | set.shift_down(1) | set.shift_up(stride) | set.shift_down(stride);
+        spread & self.points & !set
     }
 
-    // except if it results in the capture of another group
-    let mut check_capture = |row, col| {
-        let actual_color = match color {
-            1 => 2,
-            2 => 1,
-            _ => 0,
+    /// Get the group of connected stones at an intersection, which is empty if it has no stone
+    pub fn group(&self, x: usize, y: usize) -> Bitboard {
+        let stones = match self.get(x, y) {
+            Intersection::Black => self.black,
+            Intersection::White => self.white,
+            Intersection::Empty => return Bitboard::new(),
         };
-        get_liberties(row, col, actual_color, &mut board);
-        match &board[row][col] {
-            Intersection::Black(group) => {
-                if actual_color == 1 && group.liberties.len() == 1 {
-                    is_valid = true;
-                }
-            },
-            Intersection::White(group) => {
-                if actual_color == 2 && group.liberties.len() == 1 {
-                    is_valid = true;
-                }
-            },
-            _ => (),
+        let mut group = Bitboard::new();
+        group.insert(self.index(x, y));
+        loop {
+            let grown = group | (self.neighbors(group) & stones);
+            if grown == group {
+                return group;
+            }
+            group = grown;
         }
-    };
-    if x > 0 {
-        check_capture(x - 1, y);
     }
-    if x < ROWS - 1 {
-        check_capture(x + 1, y);
+
+    /// Get the liberties of a group
+    pub fn liberties(&self, group: Bitboard) -> Bitboard {
+        self.neighbors(group) & self.empty()
     }
-    if y > 0 {
-        check_capture(x, y - 1);
+
+    /// Iterate over the coordinates of a set of intersections
+    pub fn points(&self, set: Bitboard) -> impl Iterator<Item = (usize, usize)> {
+        let stride = self.cols + 1;
+        set.bits().map(move |i| (i / stride, i % stride))
     }
-    if y < COLS - 1 {
-        check_capture(x, y + 1);
+
+    fn clear(&mut self, set: Bitboard) {
+        self.black = self.black & !set;
+        self.white = self.white & !set;
     }
 
-    // check for ko
-    if is_valid {
-        is_valid = simulate_ko(x, y, color, &board, &mut hash);
+    // precondition: the intersection is empty
+    /// Place a stone and remove the groups it captures, then its own group if it is left without
+    /// liberties. Return the captured stones and the suicided stones
+    pub fn place(&mut self, x: usize, y: usize, color: usize) -> (Bitboard, Bitboard) {
+        self.set(x, y, Intersection::stone(color));
+
+        // find neighboring groups of the other color left without liberties
+        let opponent = if color == BLACK { Intersection::White } else { Intersection::Black };
+        let mut stone = Bitboard::new();
+        stone.insert(self.index(x, y));
+        let mut captured = Bitboard::new();
+        for (row, col) in self.points(self.neighbors(stone)) {
+            if self.get(row, col) != opponent || captured.contains(self.index(row, col)) {
+                continue;
+            }
+            let group = self.group(row, col);
+            if self.liberties(group).is_empty() {
+                captured = captured | group;
+            }
+        }
+        self.clear(captured);
+
+        // then remove the group of the move if it has no liberties left
+        let group = self.group(x, y);
+        let suicide = if self.liberties(group).is_empty() { group } else { Bitboard::new() };
+        self.clear(suicide);
+        (captured, suicide)
     }
+}
 
-    is_valid
+/// Set intersections to a color without playing a move
+pub fn set_stones(points: &[(usize, usize)], color: usize, board: &mut [Vec<Intersection>]) {
+    for &(x, y) in points {
+        board[x][y] = Intersection::stone(color);
+    }
 }
 
-/// Simulate a move to check for ko
-pub fn simulate_ko(x: usize, y: usize, color: usize, board: &Vec<Vec<Intersection>>, hash: &mut Zobrist) -> bool {
-    // simulate the move
-    let mut sim_board = board.clone();
-    if color == 1 {
-        sim_board[x][y] = Intersection::Black(Group { intersections: HashSet::new(), liberties: HashSet::new() });
-    } else {
-        sim_board[x][y] = Intersection::White(Group { intersections: HashSet::new(), liberties: HashSet::new() });
-    }
-    get_intersections(x, y, color, &mut sim_board);
-    get_liberties(x, y, color, &mut sim_board);
-
-    // get any pieces that need to be removed
-    let mut to_remove: Vec<(usize, usize)> = vec![];
-    let mut check_remove = |row: usize, col: usize| {
-        if sim_board[row][col] == Intersection::Empty {
-            return;
-        }
-        let actual_color = match color {
-            1 => 2,
-            2 => 1,
-            _ => 0,
-        };
-        get_liberties(row, col, actual_color, &mut sim_board);
-        match &sim_board[row][col] {
-            Intersection::Black(group) => {
-                if actual_color == 1 && group.liberties.len() == 0 { 
-                    to_remove.extend(group.intersections.iter());
-                }
-            },
-            Intersection::White(group) => {
-                if actual_color == 2 && group.liberties.len() == 0 {
-                    to_remove.extend(group.intersections.iter());
-                }
-            },
-            _ => (),
+/// Get the group of connected stones at an intersection of a board
+pub fn get_group(x: usize, y: usize, board: &[Vec<Intersection>]) -> Vec<(usize, usize)> {
+    let stones = Stones::from_board(board);
+    stones.points(stones.group(x, y)).collect()
+}
+
+/// Intersections cleared by a move, as the captured stones and the suicided stones
+pub type RemovedStones = (Vec<(usize, usize)>, Vec<(usize, usize)>);
+
+/// Place a stone and remove the groups it captures, then its own group if it is left without
+/// liberties, whether or not the move is legal, as game records may follow other rules. Return
+/// the captured intersections and the suicided intersections
+pub fn play_move(
+    x: usize,
+    y: usize,
+    color: usize,
+    board: &mut [Vec<Intersection>],
+) -> RemovedStones {
+    let mut stones = Stones::from_board(board);
+    let (captured, suicide) = stones.place(x, y, color);
+    let captured: Vec<(usize, usize)> = stones.points(captured).collect();
+    let suicide: Vec<(usize, usize)> = stones.points(suicide).collect();
+    board[x][y] = Intersection::stone(color);
+    set_stones(&captured, EMPTY, board);
+    set_stones(&suicide, EMPTY, board);
+    (captured, suicide)
+}
+
+/// Reason a move is illegal
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum IllegalMove {
+    /// the intersection already has a stone
+    Occupied,
+    /// the move leaves its own group without liberties, and the rules forbid it
+    Suicide,
+    /// the move immediately retakes a ko
+    Ko,
+    /// the move recreates an earlier position forbidden by the superko rule
+    Superko,
+    /// white moved or black passed while black is still placing free handicap stones
+    Handicap,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IllegalMove::Occupied => write!(f, "the intersection is occupied"),
+            IllegalMove::Suicide => write!(f, "suicide is not allowed"),
+            IllegalMove::Ko => write!(f, "the ko cannot be retaken immediately"),
+            IllegalMove::Superko => write!(f, "the move repeats an earlier position"),
+            IllegalMove::Handicap => write!(f, "black is still placing handicap stones"),
         }
-    };
-    if x > 0 {
-        check_remove(x - 1, y);
     }
-    if x < ROWS - 1 {
-        check_remove(x + 1, y);
+}
+
+/// The changes made by a legal move
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MoveOutcome {
+    point: (usize, usize),
+    color: usize,
+    /// the stones on the board after the move
+    stones: Stones,
+    captured: Bitboard,
+    suicide: Bitboard,
+    hash: u64,
+}
+
+impl MoveOutcome {
+    /// Get the stones captured by the move
+    pub fn captured(&self) -> Vec<(usize, usize)> {
+        self.stones.points(self.captured).collect()
     }
-    if y > 0 {
-        check_remove(x, y - 1);
+
+    /// Get the stones of the move's own group removed as a suicide
+    pub fn suicide(&self) -> Vec<(usize, usize)> {
+        self.stones.points(self.suicide).collect()
     }
-    if y < COLS - 1 {
-        check_remove(x, y + 1);
+
+    /// Get the hash of the board after the move
+    pub fn hash(&self) -> u64 {
+        self.hash
     }
 
-    // update the board to make removed intersections empty
-    for i in to_remove.iter() {
-        sim_board[i.0][i.1] = Intersection::Empty;
+    /// Make the move on the board it was played on
+    pub fn apply(&self, board: &mut [Vec<Intersection>]) {
+        let (x, y) = self.point;
+        board[x][y] = Intersection::stone(self.color);
+        for (x, y) in self.stones.points(self.captured | self.suicide) {
+            board[x][y] = Intersection::Empty;
+        }
     }
+}
 
-    // check for ko
-    let is_ko = hash.update(&sim_board);
-    is_ko
+/// A position to play a move in: the stones on the board, with its hash, the rules, and the
+/// earlier positions that decide whether a move is legal
+pub struct Position<'a, H> {
+    stones: Stones,
+    hash: &'a Zobrist,
+    ruleset: &'a Ruleset,
+    history: H,
 }
 
-/// Simulate a move on a board
-pub fn simulate_move(x: usize, y: usize, color: usize, mut board: Vec<Vec<Intersection>>) {
-    if color == 1 {
-        board[x][y] = Intersection::Black(Group { intersections: HashSet::new(), liberties: HashSet::new() });
-    } else {
-        board[x][y] = Intersection::White(Group { intersections: HashSet::new(), liberties: HashSet::new() });
+impl<'a, H> Position<'a, H>
+where
+    H: Iterator<Item = PastPosition> + Clone,
+{
+    /// Create the position of a board with its hash, given the positions from this one back to
+    /// the start of the game
+    pub fn new(
+        board: &[Vec<Intersection>],
+        hash: &'a Zobrist,
+        ruleset: &'a Ruleset,
+        history: H,
+    ) -> Position<'a, H> {
+        Position {
+            stones: Stones::from_board(board),
+            hash,
+            ruleset,
+            history,
+        }
     }
 
-    // update intersections and liberties for the move
-    get_intersections(x, y, color, &mut board);
-    get_liberties(x, y, color, &mut board);
+    /// Play a move without changing the position, returning what it changes or why it is illegal
+    pub fn play(&self, color: usize, point: (usize, usize)) -> Result<MoveOutcome, IllegalMove> {
+        let (x, y) = point;
+        if self.stones.get(x, y) != Intersection::Empty {
+            return Err(IllegalMove::Occupied);
+        }
+        let mut stones = self.stones;
+        let (captured, suicide) = stones.place(x, y, color);
 
-    // get any pieces that need to be removed
-    let mut to_remove: Vec<(usize, usize)> = vec![];
-    let mut check_remove = |row: usize, col: usize| {
-        if board[row][col] == Intersection::Empty {
-            return;
+        // prevent suicide, except of more than the move itself if the rules permit it
+        if !suicide.is_empty() && (!self.ruleset.suicide || suicide.count() == 1) {
+            return Err(IllegalMove::Suicide);
         }
-        let actual_color = match color {
-            1 => 2,
-            2 => 1,
-            _ => 0,
-        };
-        get_liberties(row, col, actual_color, &mut board);
-        match &board[row][col] {
-            Intersection::Black(group) => {
-                if actual_color == 1 && group.liberties.len() == 0 {
-                    to_remove.extend(group.intersections.iter());
-                }
-            },
-            Intersection::White(group) => {
-                if actual_color == 2 && group.liberties.len() == 0 {
-                    to_remove.extend(group.intersections.iter());
-                }
-            },
-            _ => (),
+
+        // recreating the position before the last move retakes a ko, and any other repetition
+        // breaks superko
+        let new_hash = self.hash.calculate_stones(&stones);
+        if self.hash.is_repeat(self.ruleset.ko, new_hash, color, self.history.clone()) {
+            if self.history.clone().nth(1).map_or(false, |past| past.hash == new_hash) {
+                return Err(IllegalMove::Ko);
+            }
+            return Err(IllegalMove::Superko);
         }
-    };
-    if x > 0 {
-        check_remove(x - 1, y);
+
+        Ok(MoveOutcome {
+            point,
+            color,
+            stones,
+            captured,
+            suicide,
+            hash: new_hash,
+        })
     }
-    if x < ROWS - 1 {
-        check_remove(x + 1, y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a board from rows of B, W and . characters
+    fn board(rows: &[&str]) -> Vec<Vec<Intersection>> {
+        rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        'B' => Intersection::Black,
+                        'W' => Intersection::White,
+                        _ => Intersection::Empty,
+                    })
+                    .collect()
+            })
+            .collect()
     }
-    if y > 0 {
-        check_remove(x, y - 1);
+
+    fn ruleset(ko: KoRule, suicide: bool) -> Ruleset {
+        Ruleset {
+            ko,
+            suicide,
+            ..Ruleset::default()
+        }
     }
-    if y < COLS - 1 {
-        check_remove(x, y + 1);
+
+    /// A game played from a board, keeping the hash and the positions reached so far
+    struct Record {
+        board: Vec<Vec<Intersection>>,
+        hash: Zobrist,
+        history: Vec<PastPosition>,
     }
 
-    // update the board to make removed intersections empty
-    for i in to_remove.iter() {
-        board[i.0][i.1] = Intersection::Empty;
+    impl Record {
+        fn new(rows: &[&str]) -> Record {
+            let board = board(rows);
+            let mut hash = Zobrist::new(board.len(), board[0].len());
+            let start = PastPosition {
+                hash: hash.update(&board),
+                to_play: BLACK,
+                player: None,
+            };
+            Record {
+                board,
+                hash,
+                history: vec![start],
+            }
+        }
+
+        /// Check a move against the positions so far, newest first as the game tree gives them
+        fn check(
+            &self,
+            rules: &Ruleset,
+            color: usize,
+            point: (usize, usize),
+        ) -> Result<MoveOutcome, IllegalMove> {
+            let history = self.history.iter().rev().copied();
+            Position::new(&self.board, &self.hash, rules, history).play(color, point)
+        }
+
+        /// Play a move if it is legal, returning the number of stones it captured
+        fn play(
+            &mut self,
+            rules: &Ruleset,
+            color: usize,
+            point: (usize, usize),
+        ) -> Result<usize, IllegalMove> {
+            let outcome = self.check(rules, color, point)?;
+            outcome.apply(&mut self.board);
+            self.history.push(PastPosition {
+                hash: self.hash.play(&outcome),
+                to_play: if color == BLACK { WHITE } else { BLACK },
+                player: Some(color),
+            });
+            Ok(outcome.captured().len())
+        }
+
+        fn pass(&mut self, color: usize) {
+            self.history.push(PastPosition {
+                hash: self.hash.get_hash(),
+                to_play: if color == BLACK { WHITE } else { BLACK },
+                player: None,
+            });
+        }
     }
-}
 
+    /// Three kos side by side, each a white stone at (1, 1) that black can capture at (1, 2),
+    /// then the same shape five and ten columns along
+    const KOS: [&str; 3] = [
+        ".BW...BW...BW..",
+        "BW.W.BW.W.BW.W.",
+        ".BW...BW...BW..",
+    ];
+
+    #[test]
+    fn stones_cannot_go_on_occupied_intersections() {
+        let record = Record::new(&["B..", "...", "..W"]);
+        let rules = ruleset(KoRule::Simple, false);
+        assert_eq!(record.check(&rules, WHITE, (0, 0)).err(), Some(IllegalMove::Occupied));
+        assert_eq!(record.check(&rules, BLACK, (2, 2)).err(), Some(IllegalMove::Occupied));
+        assert!(record.check(&rules, BLACK, (1, 1)).is_ok());
+    }
+
+    #[test]
+    fn suicide_of_a_single_stone_is_always_illegal() {
+        let record = Record::new(&[".W.", "W..", "..."]);
+        let new_zealand = ruleset(KoRule::Situational, true);
+        let japanese = ruleset(KoRule::Simple, false);
+        let suicide = Some(IllegalMove::Suicide);
+        assert_eq!(record.check(&new_zealand, BLACK, (0, 0)).err(), suicide);
+        assert_eq!(record.check(&japanese, BLACK, (0, 0)).err(), suicide);
+    }
+
+    #[test]
+    fn suicide_of_several_stones_follows_the_rules() {
+        let record = Record::new(&["B.W..", "WW...", ".....", ".....", "....."]);
+        let japanese = ruleset(KoRule::Simple, false);
+        assert_eq!(record.check(&japanese, BLACK, (0, 1)).err(), Some(IllegalMove::Suicide));
+
+        let new_zealand = ruleset(KoRule::Situational, true);
+        let outcome = record.check(&new_zealand, BLACK, (0, 1)).unwrap();
+        assert!(outcome.captured().is_empty());
+        assert_eq!(outcome.suicide(), vec![(0, 0), (0, 1)]);
+        let mut board = record.board.clone();
+        outcome.apply(&mut board);
+        assert_eq!(board[0][0], Intersection::Empty);
+        assert_eq!(board[0][1], Intersection::Empty);
+        assert_eq!(record.hash.calculate(&board), outcome.hash());
+    }
+
+    #[test]
+    fn a_ko_cannot_be_retaken_immediately() {
+        let mut record = Record::new(&KOS);
+        let rules = ruleset(KoRule::Simple, false);
+        assert_eq!(record.play(&rules, BLACK, (1, 2)), Ok(1));
+        assert_eq!(record.board[1][1], Intersection::Empty);
+        assert_eq!(record.check(&rules, WHITE, (1, 1)).err(), Some(IllegalMove::Ko));
+
+        // after a move elsewhere the ko can be taken back
+        assert_eq!(record.play(&rules, WHITE, (0, 4)), Ok(0));
+        assert_eq!(record.play(&rules, BLACK, (2, 4)), Ok(0));
+        assert_eq!(record.play(&rules, WHITE, (1, 1)), Ok(1));
+    }
+
+    #[test]
+    fn a_triple_ko_repeats_under_superko_rules_only() {
+        // the first two kos start with their white stone, and black has taken the third
+        let mut rows = KOS;
+        rows[1] = "BW.W.BW.W.B.BW.";
+
+        // each player takes a different ko in turn, until white would recreate the start
+        let cycle = [
+            (BLACK, (1, 2)),
+            (WHITE, (1, 11)),
+            (BLACK, (1, 7)),
+            (WHITE, (1, 1)),
+            (BLACK, (1, 12)),
+        ];
+        for ko in [KoRule::Simple, KoRule::Positional] {
+            let rules = ruleset(ko, false);
+            let mut record = Record::new(&rows);
+            for &(color, point) in cycle.iter() {
+                assert_eq!(record.play(&rules, color, point), Ok(1));
+            }
+            let repeat = record.check(&rules, WHITE, (1, 6));
+            match ko {
+                KoRule::Simple => assert!(repeat.is_ok()),
+                _ => assert_eq!(repeat.err(), Some(IllegalMove::Superko)),
+            }
+        }
+    }
+
+    #[test]
+    fn natural_situational_superko_allows_repeats_reached_by_passing() {
+        // black takes the ko and both players pass, so white taking it back recreates the start
+        // with black to move, which no move of white created
+        for ko in [KoRule::Situational, KoRule::NaturalSituational] {
+            let rules = ruleset(ko, false);
+            let mut record = Record::new(&KOS);
+            assert_eq!(record.play(&rules, BLACK, (1, 2)), Ok(1));
+            record.pass(WHITE);
+            record.pass(BLACK);
+            let retake = record.check(&rules, WHITE, (1, 1));
+            match ko {
+                KoRule::NaturalSituational => assert!(retake.is_ok()),
+                _ => assert_eq!(retake.err(), Some(IllegalMove::Superko)),
+            }
+        }
+    }
+
+    #[test]
+    fn stones_are_not_next_to_the_start_of_the_next_row() {
+        let mut stones = Stones::new(MAX_SIZE, MAX_SIZE);
+        let last = MAX_SIZE - 1;
+        let liberties = |stones: &Stones, x, y| -> Vec<(usize, usize)> {
+            stones.points(stones.liberties(stones.group(x, y))).collect()
+        };
+        stones.set(0, last, Intersection::Black);
+        stones.set(1, 0, Intersection::Black);
+        assert_eq!(stones.group(0, last).count(), 1);
+        assert_eq!(liberties(&stones, 0, last), vec![(0, last - 1), (1, last)]);
+        assert_eq!(liberties(&stones, 1, 0), vec![(0, 0), (1, 1), (2, 0)]);
+
+        // surrounding the stone at the end of the row captures it alone
+        stones.place(0, last - 1, WHITE);
+        let (captured, suicide) = stones.place(1, last, WHITE);
+        assert_eq!(stones.points(captured).collect::<Vec<_>>(), vec![(0, last)]);
+        assert!(suicide.is_empty());
+        assert_eq!(stones.get(1, 0), Intersection::Black);
+
+        // the same holds in the last row, at the end of the bitboard
+        stones.set(last, last, Intersection::White);
+        assert_eq!(liberties(&stones, last, last), vec![(last - 1, last), (last, last - 1)]);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod error;
+pub mod game;
+pub mod go;
+pub mod rules;
+pub mod score;
+pub mod sgf;
@@ -1,37 +1,108 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-pub mod game;
-pub mod go;
-
-use game::{Game, Node, Saved, BLACK, WHITE};
-use go::{
-    get_intersections, get_liberties, simulate_ko, Board, Group, Hash, Intersection, Tree, Zobrist,
-    COLS, ROWS,
+use app_lib::error::Error;
+use app_lib::{score, sgf};
+use app_lib::game::{
+    Annotation, Emphasis, Game, GameInfo, Judgement, Markup, MoveAnnotation, NodeId, Saved, Setup,
+    TreeView, Variation, BLACK, EMPTY, WHITE,
+};
+use app_lib::go::{
+    empty_board, get_group, play_move, set_stones, Board, Hash, IllegalMove, Intersection, KoRule,
+    Position, Tree, Zobrist, DEFAULT_SIZE, MAX_SIZE,
 };
+use app_lib::rules::{handicap_points, Placement, Ruleset};
+use app_lib::score::{Score, Territory};
+use app_lib::sgf::{parse_point, parse_size, GameTree, SgfNode};
 use std::collections::HashSet;
 use std::fs;
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
+
+/// Pieces added to and intersections cleared from the board when moving between nodes
+type BoardChange = (Vec<(usize, usize, usize)>, Vec<(usize, usize)>);
 
 #[tauri::command]
-fn get_rows() -> usize {
-    ROWS
+fn get_rows(board: tauri::State<Board>) -> Result<usize, Error> {
+    let board = board.pieces.lock()?;
+    Ok(board.len())
 }
 
 #[tauri::command]
-fn get_cols() -> usize {
-    COLS
+fn get_cols(board: tauri::State<Board>) -> Result<usize, Error> {
+    let board = board.pieces.lock()?;
+    Ok(board[0].len())
 }
 
+/// Start a new game, optionally with new board dimensions
 #[tauri::command]
-fn reset(board: tauri::State<Board>, hash: tauri::State<Hash>) {
-    let mut board = board.pieces.lock().unwrap();
-    let mut hash = hash.zobrist.lock().unwrap();
-    *board = vec![vec![Intersection::Empty; COLS]; ROWS];
-    *hash = Zobrist::new();
+fn reset(
+    rows: Option<usize>,
+    cols: Option<usize>,
+    board: tauri::State<Board>,
+    hash: tauri::State<Hash>,
+    tree: tauri::State<Tree>,
+) -> Result<(), Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+
+    // keep the current dimensions unless valid new ones are given
+    let rows = rows.filter(|&n| n > 0 && n <= MAX_SIZE).unwrap_or(board.len());
+    let cols = cols.filter(|&n| n > 0 && n <= MAX_SIZE).unwrap_or(board[0].len());
+    *board = empty_board(rows, cols);
+    *hash = Zobrist::new(rows, cols);
+    *game = Game::new(rows, cols);
+    Ok(())
+}
+
+/// Start a new game with the current dimensions and rules, and a number of handicap stones that
+/// are either placed on fixed points or left for black to place. Return the stones placed
+#[tauri::command]
+fn new_game(
+    handicap: usize,
+    placement: Option<Placement>,
+    board: tauri::State<Board>,
+    hash: tauri::State<Hash>,
+    tree: tauri::State<Tree>,
+) -> Result<Vec<(usize, usize, usize)>, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let (rows, cols) = (board.len(), board[0].len());
+    let ruleset = game.info.ruleset.clone();
+    *board = empty_board(rows, cols);
+    *hash = Zobrist::new(rows, cols);
+    *game = Game::new(rows, cols);
+    game.info.komi = ruleset.komi;
+    game.info.ruleset = ruleset;
+
+    // a single handicap stone is just black moving first
+    if handicap < 2 {
+        return Ok(Vec::new());
+    }
+    game.handicap = handicap;
+    game.info.komi = 0.5;
+
+    // place stones on fixed points if the board has them, and otherwise let black place them
+    let points = match placement.unwrap_or(game.info.ruleset.placement) {
+        Placement::Fixed => handicap_points(rows, cols, handicap),
+        Placement::Free => None,
+    };
+    match points {
+        Some(points) => {
+            set_stones(&points, BLACK, &mut board);
+            let root_hash = hash.update(&board);
+            game.set_root(board.clone(), root_hash);
+            Ok(points.into_iter().map(|(x, y)| (x, y, BLACK)).collect())
+        }
+        None => {
+            game.free_handicap = handicap;
+            Ok(Vec::new())
+        }
+    }
 }
 
-/// Check if a given move is valid
+/// Check if a given move is legal, returning the reason if it is not
 #[tauri::command]
 fn validate(
     x: usize,
@@ -39,99 +110,26 @@ fn validate(
     color: usize,
     board: tauri::State<Board>,
     hash: tauri::State<Hash>,
-) -> bool {
-    let mut board = board.pieces.lock().unwrap();
-    let mut hash = hash.zobrist.lock().unwrap();
-    let intersection = &board[x][y];
-    let mut is_valid: bool;
-
-    // check for existing piece
-    match intersection {
-        Intersection::Empty => is_valid = true,
-        _ => is_valid = false,
-    }
-    if !is_valid {
-        return is_valid;
-    }
-
-    // prevent suicide by checking if group has any liberties
-    is_valid = false;
-    let mut check_liberty = |row: usize, col: usize| {
-        if board[row][col] == Intersection::Empty {
-            is_valid = true;
-        } else if matches!(board[row][col], Intersection::Black(_)) && color == 1 {
-            get_liberties(row, col, color, &mut board);
-            if let Intersection::Black(group) = &board[row][col] {
-                if group.liberties.len() > 1 {
-                    is_valid = true;
-                }
-            }
-        } else if matches!(board[row][col], Intersection::White(_)) && color == 2 {
-            get_liberties(row, col, color, &mut board);
-            if let Intersection::White(group) = &board[row][col] {
-                if group.liberties.len() > 1 {
-                    is_valid = true;
-                }
-            }
-        }
-    };
-    if x > 0 {
-        check_liberty(x - 1, y);
-    }
-    if x < ROWS - 1 {
-        check_liberty(x + 1, y);
-    }
-    if y > 0 {
-        check_liberty(x, y - 1);
-    }
-    if y < COLS - 1 {
-        check_liberty(x, y + 1);
-    }
+    tree: tauri::State<Tree>,
+) -> Result<Option<IllegalMove>, Error> {
+    let board = board.pieces.lock()?;
+    let hash = hash.zobrist.lock()?;
+    let game = tree.game.lock()?;
+    check_bounds(x, y, &board)?;
 
-    // except if it results in the capture of another group
-    let mut check_capture = |row, col| {
-        let actual_color = match color {
-            1 => 2,
-            2 => 1,
-            _ => 0,
-        };
-        get_liberties(row, col, actual_color, &mut board);
-        match &board[row][col] {
-            Intersection::Black(group) => {
-                if actual_color == 1 && group.liberties.len() == 1 {
-                    is_valid = true;
-                }
-            }
-            Intersection::White(group) => {
-                if actual_color == 2 && group.liberties.len() == 1 {
-                    is_valid = true;
-                }
-            }
-            _ => (),
+    // black places free handicap stones on any empty intersection
+    if game.is_placing_handicap() {
+        if color != BLACK {
+            return Ok(Some(IllegalMove::Handicap));
         }
-    };
-    if x > 0 {
-        check_capture(x - 1, y);
-    }
-    if x < ROWS - 1 {
-        check_capture(x + 1, y);
-    }
-    if y > 0 {
-        check_capture(x, y - 1);
-    }
-    if y < COLS - 1 {
-        check_capture(x, y + 1);
-    }
-
-    // check for ko
-    if is_valid {
-        is_valid = simulate_ko(x, y, color, &board, &mut hash);
+        return Ok((board[x][y] != Intersection::Empty).then(|| IllegalMove::Occupied));
     }
 
-    is_valid
+    let position = Position::new(&board, &hash, &game.info.ruleset, game.history());
+    Ok(position.play(color, (x, y)).err())
 }
 
-/// Wrapper function for tauri to handle a move
+/// Wrapper function for tauri to handle a move, which must be legal
 #[tauri::command]
 fn tauri_move(
     x: usize,
@@ -139,86 +137,345 @@ fn tauri_move(
     color: usize,
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
-) -> Vec<(usize, usize)> {
-    let piece: Vec<(usize, usize)> = handle_move(x, y, color, &board, &tree);
-    return piece;
+    hash: tauri::State<Hash>,
+) -> Result<Vec<(usize, usize)>, Error> {
+    check_bounds(x, y, &board.pieces.lock()?)?;
+    handle_move(x, y, color, true, &board, &tree, &hash)
 }
 
-// precondition: move is valid
-/// Handle a move by returning a list of intersections to remove
+/// Check that an intersection is on the board
+fn check_bounds(x: usize, y: usize, board: &[Vec<Intersection>]) -> Result<(), Error> {
+    if x < board.len() && y < board[0].len() {
+        Ok(())
+    } else {
+        Err(Error::OutOfBounds(x, y))
+    }
+}
+
+// precondition: the move is on the board
+/// Handle a move by returning a list of intersections to remove. An illegal move is an error if
+/// it is checked, and is otherwise played anyway, as game records may follow other rules
 fn handle_move(
     x: usize,
     y: usize,
     color: usize,
+    check: bool,
     board: &tauri::State<Board>,
     tree: &tauri::State<Tree>,
-) -> Vec<(usize, usize)> {
-    let mut board = board.pieces.lock().unwrap();
-    if color == 1 {
-        board[x][y] = Intersection::Black(Group {
-            intersections: HashSet::new(),
-            liberties: HashSet::new(),
-        });
+    hash: &tauri::State<Hash>,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+
+    // place a free handicap stone on the root instead of adding a move
+    if game.is_placing_handicap() {
+        if check && color != BLACK {
+            return Err(IllegalMove::Handicap.into());
+        }
+        if check && board[x][y] != Intersection::Empty {
+            return Err(IllegalMove::Occupied.into());
+        }
+        set_stones(&[(x, y)], BLACK, &mut board);
+        let root_hash = hash.update(&board);
+        game.set_root(board.clone(), root_hash);
+        game.free_handicap -= 1;
+        return Ok(Vec::new());
+    }
+
+    // place the stone, removing captures and a suicide, where a checked move already knows the
+    // hash of the board after it
+    let (mut to_remove, suicide, new_hash) = if check {
+        let position = Position::new(&board, &hash, &game.info.ruleset, game.history());
+        let outcome = position.play(color, (x, y))?;
+        outcome.apply(&mut board);
+        (outcome.captured(), outcome.suicide(), hash.play(&outcome))
     } else {
-        board[x][y] = Intersection::White(Group {
-            intersections: HashSet::new(),
-            liberties: HashSet::new(),
-        });
+        let (captured, suicide) = play_move(x, y, color, &mut board);
+        (captured, suicide, hash.update(&board))
+    };
+
+    // update the game nodes and the prisoners taken, where suicided stones go to the opponent
+    let captured = if color == BLACK {
+        (to_remove.len(), suicide.len())
+    } else {
+        (suicide.len(), to_remove.len())
+    };
+    to_remove.extend(suicide);
+    game.add_node(&board, new_hash, Some((x, y)), color, captured, to_remove.clone());
+
+    Ok(to_remove)
+}
+
+/// Wrapper function for tauri to handle a pass, returning whether the game has ended
+#[tauri::command]
+fn tauri_pass(
+    color: usize,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+) -> Result<bool, Error> {
+    // black cannot pass before placing every free handicap stone
+    if tree.game.lock()?.is_placing_handicap() {
+        return Err(IllegalMove::Handicap.into());
     }
+    handle_pass(color, &board, &tree)?;
+    let game = tree.game.lock()?;
+    Ok(game.is_over())
+}
 
-    // update intersections and liberties for the move
-    get_intersections(x, y, color, &mut board);
-    get_liberties(x, y, color, &mut board);
+/// Handle a pass by adding a node with an unchanged board
+fn handle_pass(
+    color: usize,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+) -> Result<(), Error> {
+    // the board is unchanged, so the position hash stays the same as well
+    let board = board.pieces.lock()?;
+    let mut game = tree.game.lock()?;
+    let hash = game.curr_node().get_hash();
+    game.add_node(&board, hash, None, color, (0, 0), Vec::new());
+    Ok(())
+}
 
-    // get any pieces that need to be removed
-    let mut to_remove: Vec<(usize, usize)> = vec![];
-    let mut check_remove = |row: usize, col: usize| {
-        if board[row][col] == Intersection::Empty {
-            return;
-        }
-        let actual_color = match color {
-            1 => 2,
-            2 => 1,
-            _ => 0,
-        };
-        get_liberties(row, col, actual_color, &mut board);
-        match &board[row][col] {
-            Intersection::Black(group) => {
-                if actual_color == 1 && group.liberties.len() == 0 {
-                    to_remove.extend(group.intersections.iter());
-                }
-            }
-            Intersection::White(group) => {
-                if actual_color == 2 && group.liberties.len() == 0 {
-                    to_remove.extend(group.intersections.iter());
-                }
+/// Place or remove a setup stone at the current position without playing a move, where placing
+/// a stone on one of the same color removes it. Return the color now at the intersection
+#[tauri::command]
+fn tauri_setup(
+    x: usize,
+    y: usize,
+    color: usize,
+    board: tauri::State<Board>,
+    hash: tauri::State<Hash>,
+    tree: tauri::State<Tree>,
+) -> Result<usize, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    check_bounds(x, y, &board)?;
+    let color = match (&board[x][y], color) {
+        (Intersection::Black, BLACK) | (Intersection::White, WHITE) => EMPTY,
+        _ => color,
+    };
+    let previous = board.clone();
+    set_stones(&[(x, y)], color, &mut board);
+    let new_hash = hash.update(&board);
+
+    // stones set before the first move go on the root, and a setup node at the end of a line is
+    // edited in place and removed once it changes nothing and has no annotation or markup, while
+    // anywhere else a new setup node is added
+    let (parent, setup, has_children) = {
+        let curr = game.curr_node();
+        (curr.get_parent(), curr.get_setup(), !curr.get_children().is_empty())
+    };
+    match parent {
+        None if !has_children => game.set_root(board.clone(), new_hash),
+        Some(parent) if !has_children && game.curr_node().is_setup() => {
+            let setup = Setup::diff(&game.get_board(parent), &board, setup.player);
+            let is_bare = {
+                let curr = game.curr_node();
+                curr.get_annotation() == Annotation::default()
+                    && curr.get_markup().is_empty()
+                    && curr.get_dead().is_empty()
+            };
+            if setup.is_empty() && is_bare {
+                game.pop_node();
+            } else {
+                game.set_setup(&board, new_hash, setup);
             }
-            _ => (),
         }
-    };
-    if x > 0 {
-        check_remove(x - 1, y);
-    }
-    if x < ROWS - 1 {
-        check_remove(x + 1, y);
+        _ => game.add_setup(&board, new_hash, Setup::diff(&previous, &board, None)),
     }
-    if y > 0 {
-        check_remove(x, y - 1);
+    Ok(color)
+}
+
+/// Handle a setup node by adding and removing its stones, then adding it to the game tree
+fn handle_setup(
+    setup: Setup,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<(), Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    set_stones(&setup.empty, EMPTY, &mut board);
+    set_stones(&setup.black, BLACK, &mut board);
+    set_stones(&setup.white, WHITE, &mut board);
+    let new_hash = hash.update(&board);
+    game.add_setup(&board, new_hash, setup);
+    Ok(())
+}
+
+/// Get the color of the player to move at the current node
+#[tauri::command]
+fn get_to_play(tree: tauri::State<Tree>) -> Result<usize, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.to_play())
+}
+
+/// Get the rules of the current game
+#[tauri::command]
+fn get_ruleset(tree: tauri::State<Tree>) -> Result<Ruleset, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.info.ruleset.clone())
+}
+
+/// Set the rules of the current game to a preset by name along with its komi, returning the
+/// preset if it exists
+#[tauri::command]
+fn set_ruleset(name: &str, tree: tauri::State<Tree>) -> Result<Option<Ruleset>, Error> {
+    let mut game = tree.game.lock()?;
+    let ruleset = match Ruleset::from_name(name) {
+        Some(ruleset) => ruleset,
+        None => return Ok(None),
+    };
+    game.info.komi = ruleset.komi;
+    game.info.ruleset = ruleset.clone();
+    Ok(Some(ruleset))
+}
+
+/// Set the rule used to forbid repeated positions, keeping the rest of the ruleset
+#[tauri::command]
+fn set_ko_rule(rule: KoRule, tree: tauri::State<Tree>) -> Result<(), Error> {
+    let mut game = tree.game.lock()?;
+    game.info.ruleset.ko = rule;
+    Ok(())
+}
+
+/// Get the information about the current game and its players
+#[tauri::command]
+fn get_game_info(tree: tauri::State<Tree>) -> Result<GameInfo, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.info.clone())
+}
+
+/// Replace the information about the current game and its players
+#[tauri::command]
+fn set_game_info(info: GameInfo, tree: tauri::State<Tree>) -> Result<(), Error> {
+    let mut game = tree.game.lock()?;
+    game.info = info;
+    Ok(())
+}
+
+/// Get the comment, name and annotations of the current node
+#[tauri::command]
+fn get_annotation(tree: tauri::State<Tree>) -> Result<Annotation, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.curr_node().get_annotation())
+}
+
+/// Replace the comment, name and annotations of the current node
+#[tauri::command]
+fn set_annotation(annotation: Annotation, tree: tauri::State<Tree>) -> Result<(), Error> {
+    let mut game = tree.game.lock()?;
+    game.curr_node_mut().set_annotation(annotation);
+    Ok(())
+}
+
+/// Get the marks drawn on the board at the current node
+#[tauri::command]
+fn get_markup(tree: tauri::State<Tree>) -> Result<Vec<Markup>, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.curr_node().get_markup())
+}
+
+/// Add a mark at the current node, and return the marks of the node
+#[tauri::command]
+fn add_markup(
+    mark: Markup,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+) -> Result<Vec<Markup>, Error> {
+    let board = board.pieces.lock()?;
+    let mut game = tree.game.lock()?;
+    let points = match &mark {
+        Markup::Arrow(from, to) | Markup::Line(from, to) => vec![*from, *to],
+        Markup::Triangle(point)
+        | Markup::Square(point)
+        | Markup::Circle(point)
+        | Markup::Cross(point)
+        | Markup::Label(point, _)
+        | Markup::Dim(point) => vec![*point],
+    };
+    for (x, y) in points {
+        check_bounds(x, y, &board)?;
     }
-    if y < COLS - 1 {
-        check_remove(x, y + 1);
+    let curr = game.curr_node_mut();
+    curr.add_markup(mark);
+    Ok(curr.get_markup())
+}
+
+/// Remove a mark at the current node, and return the marks of the node
+#[tauri::command]
+fn remove_markup(mark: Markup, tree: tauri::State<Tree>) -> Result<Vec<Markup>, Error> {
+    let mut game = tree.game.lock()?;
+    let curr = game.curr_node_mut();
+    curr.remove_markup(&mark);
+    Ok(curr.get_markup())
+}
+
+/// Get the prisoners taken by (black, white) up to the current node
+#[tauri::command]
+fn get_captures(tree: tauri::State<Tree>) -> Result<(usize, usize), Error> {
+    let game = tree.game.lock()?;
+    Ok(game.get_captures())
+}
+
+/// Check if the game has ended with two consecutive passes
+#[tauri::command]
+fn is_over(tree: tauri::State<Tree>) -> Result<bool, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.is_over())
+}
+
+/// Score the current board with its marked dead stones under the rules of the game, and record
+/// the result in the game
+#[tauri::command]
+fn score_game(board: tauri::State<Board>, tree: tauri::State<Tree>) -> Result<Score, Error> {
+    let board = board.pieces.lock()?;
+    let mut game = tree.game.lock()?;
+    let dead = game.curr_node().get_dead();
+    let info = &game.info;
+    let score = score::score(&board, info.ruleset.scoring, info.komi, game.get_captures(), &dead);
+    game.info.result = Some(score.result.clone());
+    Ok(score)
+}
+
+/// Toggle the group at an intersection between dead and alive once the game has ended,
+/// and return the updated territory
+#[tauri::command]
+fn toggle_dead(
+    x: usize,
+    y: usize,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+) -> Result<Option<Territory>, Error> {
+    let board = board.pieces.lock()?;
+    let mut game = tree.game.lock()?;
+    check_bounds(x, y, &board)?;
+    if !game.is_over() {
+        return Ok(None);
     }
 
-    // update the board to make removed intersections empty
-    for i in to_remove.iter() {
-        board[i.0][i.1] = Intersection::Empty;
+    // find every stone in the group at the intersection
+    let group: HashSet<(usize, usize)> = get_group(x, y, &board).into_iter().collect();
+    if group.is_empty() {
+        return Ok(None);
     }
 
-    // update the game nodes
-    let mut game = tree.game.lock().unwrap();
-    game.add_node(board.clone(), (x, y), color);
+    // toggle the group and recompute territory
+    let curr = game.curr_node_mut();
+    curr.toggle_dead(&group);
+    Ok(Some(Territory::new(&board, &curr.get_dead())))
+}
 
-    to_remove
+/// Get the dead stones and territory marked at the current node
+#[tauri::command]
+fn get_territory(board: tauri::State<Board>, tree: tauri::State<Tree>) -> Result<Territory, Error> {
+    let board = board.pieces.lock()?;
+    let game = tree.game.lock()?;
+    let dead = game.curr_node().get_dead();
+    Ok(Territory::new(&board, &dead))
 }
 
 /// Handle an undo move, and return (added_pieces, removed_pieces)
@@ -226,225 +483,637 @@ fn handle_move(
 fn handle_undo(
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
-) -> (Vec<(usize, usize, usize)>, Vec<(usize, usize)>) {
-    let mut change_board = board.pieces.lock().unwrap();
-    let mut game = tree.game.lock().unwrap();
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let parent = game.curr_node().get_parent();
+    go_to(parent, &mut board, &mut hash, &mut game)
+}
 
-    // update board to the parent node of current node
+#[tauri::command]
+fn handle_redo(
+    index: Option<usize>,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+
+    // follow the chosen child, or the main line
+    let child = {
+        let curr = game.curr_node();
+        let index = index.unwrap_or(curr.get_main_line());
+        curr.get_children().get(index).copied()
+    };
+    go_to(child, &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn goto_node_id(
+    id: usize,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = game.find_node(id);
+    go_to(node, &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn goto_path(
+    path: &str,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = game.find_path(path);
+    go_to(node, &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn get_path(tree: tauri::State<Tree>) -> Result<String, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.get_path(game.curr))
+}
+
+#[tauri::command]
+fn goto_move(
+    move_number: usize,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = game.find_move(move_number);
+    go_to(Some(node), &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn goto_start(
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = game.root;
+    go_to(Some(node), &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn goto_end(
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = game.find_end();
+    go_to(Some(node), &mut board, &mut hash, &mut game)
+}
+
+#[tauri::command]
+fn goto_branch(
+    forward: bool,
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let node = if forward {
+        game.find_next_branch()
+    } else {
+        game.find_previous_branch()
+    };
+    go_to(Some(node), &mut board, &mut hash, &mut game)
+}
+
+/// Make a node the current node if there is one, returning the changes to the board
+fn go_to(
+    node: Option<NodeId>,
+    board: &mut Vec<Vec<Intersection>>,
+    hash: &mut Zobrist,
+    game: &mut Game,
+) -> Result<BoardChange, Error> {
+    match node {
+        Some(node) => {
+            game.curr = node;
+            show_curr(board, hash, game)
+        }
+        None => Ok((Vec::new(), Vec::new())),
+    }
+}
+
+#[tauri::command]
+fn get_variations(tree: tauri::State<Tree>) -> Result<Vec<Variation>, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.get_variations())
+}
+
+/// Lay out the whole game tree for drawing the variation tree
+#[tauri::command]
+fn get_tree(tree: tauri::State<Tree>) -> Result<TreeView, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.get_tree())
+}
+
+/// Set the child of the current node that redo follows, returning false if there is no child at
+/// the index
+#[tauri::command]
+fn set_main_line(index: usize, tree: tauri::State<Tree>) -> Result<bool, Error> {
+    let mut game = tree.game.lock()?;
+    let is_set = game.curr_node_mut().set_main_line(index);
+    Ok(is_set)
+}
+
+/// Get the pieces to add and the intersections to clear to go from one board to another
+fn diff_boards(
+    before: &[Vec<Intersection>],
+    after: &[Vec<Intersection>],
+) -> BoardChange {
     let mut added_pieces = vec![];
     let mut removed_pieces = vec![];
-    let mut parent_node = Arc::clone(&game.curr);
-    {
-        let curr = game.curr.lock().unwrap();
-        if let Node::Move { parent, .. } = &*curr {
-            if let Some(parent) = parent {
-                parent_node = Arc::clone(&parent);
-                let parent = parent.lock().unwrap();
-                match &*parent {
-                    Node::Move { board, .. } => {
-                        // get differences between the boards
-                        for i in 0..ROWS {
-                            for j in 0..COLS {
-                                if board[i][j] != change_board[i][j] {
-                                    if board[i][j] == Intersection::Empty {
-                                        removed_pieces.push((i, j));
-                                    } else {
-                                        added_pieces.push((
-                                            i,
-                                            j,
-                                            match &board[i][j] {
-                                                Intersection::Black(_) => 1,
-                                                Intersection::White(_) => 2,
-                                                _ => 0,
-                                            },
-                                        ));
-                                    }
-                                }
-                            }
-                        }
-                        // update the actual board
-                        *change_board = board.clone();
-                    }
-                    _ => (),
+    for i in 0..after.len() {
+        for j in 0..after[i].len() {
+            if before[i][j] != after[i][j] {
+                match after[i][j] {
+                    Intersection::Empty => removed_pieces.push((i, j)),
+                    Intersection::Black => added_pieces.push((i, j, BLACK)),
+                    Intersection::White => added_pieces.push((i, j, WHITE)),
                 }
             }
         }
     }
-    game.curr = Arc::clone(&parent_node);
-
     (added_pieces, removed_pieces)
 }
 
-/// Handle a redo move, and return (added_pieces, removed_pieces)
 #[tauri::command]
-fn handle_redo(
+fn delete_node(
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
-) -> (Vec<(usize, usize, usize)>, Vec<(usize, usize)>) {
-    let mut change_board = board.pieces.lock().unwrap();
-    let mut game = tree.game.lock().unwrap();
-
-    // update board to the last child node of current node
-    // TODO: allow user to select variation of child node
-    let mut added_pieces = Vec::new();
-    let mut removed_pieces = Vec::new();
-    let mut child_node = Arc::clone(&game.curr);
-    {
-        let curr = game.curr.lock().unwrap();
-        if let Node::Move { children, .. } = &*curr {
-            // see if there are any children to redo
-            if children.len() == 0 {
-                return (added_pieces, removed_pieces);
-            }
-            child_node = Arc::clone(&children[children.len() - 1]);
-            let children = children[children.len() - 1].lock().unwrap();
-            match &*children {
-                Node::Move { board, .. } => {
-                    // get differences between the boards
-                    for i in 0..ROWS {
-                        for j in 0..COLS {
-                            if board[i][j] != change_board[i][j] {
-                                if board[i][j] == Intersection::Empty {
-                                    removed_pieces.push((i, j));
-                                } else {
-                                    added_pieces.push((
-                                        i,
-                                        j,
-                                        match &board[i][j] {
-                                            Intersection::Black(_) => BLACK,
-                                            Intersection::White(_) => WHITE,
-                                            _ => 0,
-                                        },
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    // update the actual board
-                    *change_board = board.clone();
-                }
-                _ => (),
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    if !game.delete_subtree() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    show_curr(&mut board, &mut hash, &game)
+}
+
+#[tauri::command]
+fn cut_node(
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<BoardChange, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    if !game.cut() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    show_curr(&mut board, &mut hash, &game)
+}
+
+/// Put the board of the current node on the board, returning the changes
+fn show_curr(
+    board: &mut Vec<Vec<Intersection>>,
+    hash: &mut Zobrist,
+    game: &Game,
+) -> Result<BoardChange, Error> {
+    let new_board = game.get_board(game.curr);
+    let change = diff_boards(board, &new_board);
+    *board = new_board;
+    hash.update(board);
+    Ok(change)
+}
+
+#[tauri::command]
+fn paste_node(
+    board: tauri::State<Board>,
+    tree: tauri::State<Tree>,
+    hash: tauri::State<Hash>,
+) -> Result<bool, Error> {
+    let board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    let pasted = game.paste(&mut hash);
+    // the pasted moves were replayed on the hash, so go back to the current board
+    hash.update(&board);
+    Ok(pasted)
+}
+
+#[tauri::command]
+fn has_clipboard(tree: tauri::State<Tree>) -> Result<bool, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.clipboard.is_some())
+}
+
+#[tauri::command]
+fn promote_variation(tree: tauri::State<Tree>) -> Result<(), Error> {
+    let mut game = tree.game.lock()?;
+    game.promote_variation();
+    Ok(())
+}
+
+#[tauri::command]
+fn move_variation(earlier: bool, tree: tauri::State<Tree>) -> Result<bool, Error> {
+    let mut game = tree.game.lock()?;
+    Ok(game.move_variation(earlier))
+}
+
+/// Evaluate a node from SGF
+fn eval_node(
+    node: &SgfNode,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<(), Error> {
+    // add and remove setup stones before any move in the node
+    let (rows, cols) = {
+        let board = board.pieces.lock()?;
+        (board.len(), board[0].len())
+    };
+    let on_board = |id: &str| -> Vec<(usize, usize)> {
+        node.get_points(id)
+            .into_iter()
+            .filter(|&(x, y)| x < rows && y < cols)
+            .collect()
+    };
+    let setup = Setup {
+        black: on_board("AB"),
+        white: on_board("AW"),
+        empty: on_board("AE"),
+        player: node.get("PL").and_then(parse_player),
+    };
+    // a node without a move is a setup node, even if it changes nothing
+    if !setup.is_empty() || !(node.has("B") || node.has("W")) {
+        handle_setup(setup, board, tree, hash)?;
+    }
+    eval_moves(node, board, tree, hash)
+}
+
+/// Evaluate the move or pass of an SGF node, then read its territory, annotation and markup
+/// onto the node it ends on
+fn eval_moves(
+    node: &SgfNode,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<(), Error> {
+    for (action, color) in [("B", BLACK), ("W", WHITE)] {
+        // get coordinates of the move, if the node has one for this color
+        let coords = match node.get(action) {
+            Some(coords) => coords.trim(),
+            None => continue,
+        };
+
+        // an empty value, or tt on boards up to 19x19, is a pass
+        let is_small = {
+            let board = board.pieces.lock()?;
+            board.len() <= 19 && board[0].len() <= 19
+        };
+        if coords.is_empty() || (coords == "tt" && is_small) {
+            handle_pass(color, board, tree)?;
+            continue;
+        }
+
+        // handle the move
+        let (x, y) = match parse_point(coords) {
+            Some(point) => point,
+            None => return Err(Error::InvalidSgf(format!("Invalid coordinates {}", coords))),
+        };
+        if check_bounds(x, y, &board.pieces.lock()?).is_err() {
+            return Err(Error::InvalidSgf(format!("Move {} is not on the board", coords)));
+        }
+        handle_move(x, y, color, false, board, tree, hash)?;
+    }
+
+    // recover dead stones from territory markup, as stones inside the other player's territory
+    let board = board.pieces.lock()?;
+    let mut game = tree.game.lock()?;
+    let (rows, cols) = (board.len(), board[0].len());
+    let mut dead: HashSet<(usize, usize)> = HashSet::new();
+    for (prop, color) in [("TB", WHITE), ("TW", BLACK)] {
+        for (x, y) in node.get_points(prop) {
+            let is_color = match board.get(x).and_then(|row| row.get(y)) {
+                Some(Intersection::Black) => color == BLACK,
+                Some(Intersection::White) => color == WHITE,
+                _ => false,
+            };
+            if is_color {
+                dead.insert((x, y));
             }
         }
     }
-    game.curr = Arc::clone(&child_node);
+    let curr = game.curr_node_mut();
+    if !dead.is_empty() {
+        curr.toggle_dead(&dead);
+    }
+    curr.set_annotation(parse_annotation(node));
+    curr.set_markup(parse_markup(node, rows, cols));
+    Ok(())
+}
 
-    (added_pieces, removed_pieces)
+/// Evaluate the nodes of an SGF game tree from the current node, loading each variation as a
+/// child of its last node, and return the node at the end of the first variation
+fn load_game_tree(
+    game_tree: &GameTree,
+    skip: usize,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<NodeId, Error> {
+    for node in game_tree.nodes.iter().skip(skip) {
+        eval_node(node, board, tree, hash)?;
+    }
+
+    // every variation starts from the same position
+    let branch = tree.game.lock()?.curr;
+    let mut main_line = branch;
+    for (i, variation) in game_tree.variations.iter().enumerate() {
+        goto_node(branch, board, tree, hash)?;
+        let end = load_game_tree(variation, 0, board, tree, hash)?;
+        if i == 0 {
+            main_line = end;
+        }
+    }
+
+    // the first variation is the main line in SGF
+    tree.game.lock()?.node_mut(branch).set_main_line(0);
+    Ok(main_line)
 }
 
-/// Evaluate a token from SGF
-fn eval_token(token: &str, board: &tauri::State<Board>, tree: &tauri::State<Tree>) {
-    // get action by retrieving characters before open bracket
-    let action = token.chars().take_while(|&c| c != '[').collect::<String>();
+/// Move to a node of the game tree, setting the board and hash to its position
+fn goto_node(
+    node: NodeId,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<(), Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    *board = game.get_board(node);
+    hash.update(&board);
+    game.curr = node;
+    Ok(())
+}
 
-    // if action is not "B" or "W", return early
-    if action != "B" && action != "W" {
-        return;
+/// Parse the color of a player from an SGF PL value
+fn parse_player(value: &str) -> Option<usize> {
+    match value.trim() {
+        "B" | "b" => Some(BLACK),
+        "W" | "w" => Some(WHITE),
+        _ => None,
     }
+}
 
-    // otherwise, get coordinates by retrieving characters between brackets
-    let coords = token
-        .chars()
-        .skip_while(|&c| c != '[')
-        .skip(1)
-        .take_while(|&c| c != ']')
-        .collect::<String>();
+/// Parse the comment, name and annotations of an SGF node
+fn parse_annotation(node: &SgfNode) -> Annotation {
+    let mut annotation = Annotation {
+        comment: node.get("C").map(String::from),
+        name: node.get("N").map(String::from),
+        hotspot: node.get("HO").map(Emphasis::from_sgf),
+        ..Annotation::default()
+    };
+    for (id, kind) in [
+        ("BM", MoveAnnotation::Bad),
+        ("TE", MoveAnnotation::Tesuji),
+        ("DO", MoveAnnotation::Doubtful),
+        ("IT", MoveAnnotation::Interesting),
+    ] {
+        if let Some(value) = node.get(id) {
+            annotation.move_annotation = Some((kind, Emphasis::from_sgf(value)));
+        }
+    }
+    for (id, kind) in [
+        ("GB", Judgement::GoodForBlack),
+        ("GW", Judgement::GoodForWhite),
+        ("DM", Judgement::Even),
+        ("UC", Judgement::Unclear),
+    ] {
+        if let Some(value) = node.get(id) {
+            annotation.judgement = Some((kind, Emphasis::from_sgf(value)));
+        }
+    }
+    annotation
+}
 
-    // if coordinates are not two characters long, panic and error
-    if coords.len() != 2 {
-        panic!("Invalid coordinates in SGF: {}", coords);
+/// Parse the marks of an SGF node on a board of the given dimensions, ignoring those that are not
+/// on the board
+fn parse_markup(node: &SgfNode, rows: usize, cols: usize) -> Vec<Markup> {
+    let on_board = |&(x, y): &(usize, usize)| x < rows && y < cols;
+    let mut markup = vec![];
+    for (id, mark) in [
+        ("TR", Markup::Triangle as fn((usize, usize)) -> Markup),
+        ("SQ", Markup::Square),
+        ("CR", Markup::Circle),
+        ("MA", Markup::Cross),
+        ("DD", Markup::Dim),
+    ] {
+        markup.extend(node.get_points(id).into_iter().filter(on_board).map(mark));
+    }
+    for value in node.get_all("LB") {
+        if let Some((point, text)) = value.split_once(':') {
+            if let Some(point) = parse_point(point).filter(on_board) {
+                markup.push(Markup::Label(point, text.to_string()));
+            }
+        }
     }
+    for (id, mark) in [
+        ("AR", Markup::Arrow as fn((usize, usize), (usize, usize)) -> Markup),
+        ("LN", Markup::Line),
+    ] {
+        for value in node.get_all(id) {
+            let points = value.split_once(':').and_then(|(from, to)| {
+                Some((parse_point(from).filter(on_board)?, parse_point(to).filter(on_board)?))
+            });
+            if let Some((from, to)) = points {
+                markup.push(mark(from, to));
+            }
+        }
+    }
+    markup
+}
 
-    // transform SGF coordinates to board coordinates
-    let y = coords.chars().nth(0).unwrap() as usize - 'a' as usize;
-    let x = coords.chars().nth(1).unwrap() as usize - 'a' as usize;
+/// Parse the game information from the properties of an SGF root node
+fn parse_game_info(root: &SgfNode) -> GameInfo {
+    let mut info = GameInfo::default();
+    if let Some(name) = root.get("RU") {
+        info.ruleset = Ruleset::from_name(name).unwrap_or(Ruleset {
+            name: name.to_string(),
+            ..Ruleset::default()
+        });
+        info.komi = info.ruleset.komi;
+    }
+    if let Some(komi) = root.get("KM").and_then(|komi| komi.trim().parse().ok()) {
+        info.komi = komi;
+    }
+    let get = |id: &str| root.get(id).map(String::from);
+    info.black = get("PB");
+    info.white = get("PW");
+    info.black_rank = get("BR");
+    info.white_rank = get("WR");
+    info.date = get("DT");
+    info.event = get("EV");
+    info.result = get("RE");
+    info.name = get("GN");
+    info.place = get("PC");
+    info
+}
 
-    // handle the move
-    let color = if action == "B" { BLACK } else { WHITE };
-    handle_move(x, y, color, board, tree);
+/// List the games in a file containing an SGF collection, with the information from each root
+#[tauri::command]
+fn list_sgf_file(file: &str) -> Result<Vec<GameInfo>, Error> {
+    let sgf: &str = &fs::read_to_string(file)?;
+    let trees = sgf::parse(sgf)?;
+    Ok(trees.iter().map(|tree| parse_game_info(&tree.nodes[0])).collect())
 }
 
-/// Tauri wrapper function for creating a Game from a file containing an SGF string
+/// Tauri wrapper function for creating a Game from a file containing an SGF string, loading
+/// the game at an index of the collection or else the first game
 #[tauri::command]
 fn from_sgf_file(
     file: &str,
+    index: Option<usize>,
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
-) -> Vec<(usize, usize, usize)> {
+    hash: tauri::State<Hash>,
+) -> Result<Vec<(usize, usize, usize)>, Error> {
     // load sgf from file and create a new Game
-    let sgf: &str = &fs::read_to_string(file).expect("Error: cannot read file");
-    {
-        let mut tree = tree.game.lock().unwrap();
-        *tree = Game::new();
-    }
-
-    // clear the board
-    {
-        let mut board = board.pieces.lock().unwrap();
-        *board = vec![vec![Intersection::Empty; COLS]; ROWS];
-    }
-
-    from_sgf(sgf, &board, &tree)
+    let sgf: &str = &fs::read_to_string(file)?;
+    from_sgf(sgf, index.unwrap_or(0), &board, &tree, &hash)
 }
 
-/// Create a Game from an SGF string, and return the added pieces
+/// Create a Game from the game at an index of an SGF collection, and return the added pieces.
+/// The current game is kept if the SGF does not describe a valid game
 #[tauri::command]
 fn from_sgf(
     sgf: &str,
+    index: usize,
     board: &tauri::State<Board>,
     tree: &tauri::State<Tree>,
-) -> Vec<(usize, usize, usize)> {
-    // strip first and last parantheses
-    let mut loaded = sgf.chars();
-    loaded.next();
-    loaded.next();
-    loaded.next_back();
-
-    // get tokens
-    let tokens = loaded.as_str().split(";");
-
-    // evaluate tokens
-    for token in tokens {
-        eval_token(token, &board, &tree);
+    hash: &tauri::State<Hash>,
+) -> Result<Vec<(usize, usize, usize)>, Error> {
+    // parse the collection, and find the game to load in it
+    let trees = sgf::parse(sgf)?;
+    let game_tree = trees.get(index).ok_or(Error::GameNotFound(index))?;
+    let previous = {
+        let board = board.pieces.lock()?;
+        let hash = hash.zobrist.lock()?;
+        let mut game = tree.game.lock()?;
+        let (rows, cols) = (board.len(), board[0].len());
+        (board.clone(), hash.clone(), std::mem::replace(&mut *game, Game::new(rows, cols)))
+    };
+    if let Err(err) = load_sgf_game(game_tree, board, tree, hash) {
+        let (previous_board, previous_hash, previous_game) = previous;
+        *board.pieces.lock()? = previous_board;
+        *hash.zobrist.lock()? = previous_hash;
+        *tree.game.lock()? = previous_game;
+        return Err(err);
     }
 
     // iterate through board and add pieces
     let mut added: Vec<(usize, usize, usize)> = Vec::new();
-    let board = board.pieces.lock().unwrap();
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            if let Intersection::Black(_) = board[i][j] {
+    let board = board.pieces.lock()?;
+    for i in 0..board.len() {
+        for j in 0..board[i].len() {
+            if let Intersection::Black = board[i][j] {
                 added.push((i, j, BLACK));
-            } else if let Intersection::White(_) = board[i][j] {
+            } else if let Intersection::White = board[i][j] {
                 added.push((i, j, WHITE));
             }
         }
     }
 
-    added
+    Ok(added)
 }
 
-/// Saves the current state of the board
-#[tauri::command]
-fn save_state(board: tauri::State<Board>, tree: tauri::State<Tree>, hash: tauri::State<Hash>) {
-    let hash = hash.zobrist.lock().unwrap();
-    let board = board.pieces.lock().unwrap();
-    let mut game = tree.game.lock().unwrap();
-    game.save_state(board.clone(), hash.clone());
-}
-
-/// Fancy print out the board
-fn print_board(board: &Vec<Vec<Intersection>>) {
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            match &board[i][j] {
-                Intersection::Black(_) => print!("B "),
-                Intersection::White(_) => print!("W "),
-                _ => print!(". "),
-            }
+/// Start a new game from an SGF game tree, and load its moves and variations
+fn load_sgf_game(
+    game_tree: &GameTree,
+    board: &tauri::State<Board>,
+    tree: &tauri::State<Tree>,
+    hash: &tauri::State<Hash>,
+) -> Result<(), Error> {
+    // read the board size from the root node, and start a new game with it
+    let root = &game_tree.nodes[0];
+    let (rows, cols) = match root.get("SZ") {
+        Some(size) => match parse_size(size, MAX_SIZE) {
+            Some(size) => size,
+            None => return Err(Error::InvalidSgf(format!("Invalid board size {}", size))),
+        },
+        None => (DEFAULT_SIZE, DEFAULT_SIZE),
+    };
+    {
+        let mut board = board.pieces.lock()?;
+        let mut hash = hash.zobrist.lock()?;
+        let mut game = tree.game.lock()?;
+        *board = empty_board(rows, cols);
+        *hash = Zobrist::new(rows, cols);
+        *game = Game::new(rows, cols);
+        game.info = parse_game_info(root);
+        game.handicap = root
+            .get("HA")
+            .and_then(|handicap| handicap.trim().parse().ok())
+            .unwrap_or(0);
+
+        // place the setup stones of the root node
+        for (prop, color) in [("AE", EMPTY), ("AB", BLACK), ("AW", WHITE)] {
+            let points: Vec<(usize, usize)> = root
+                .get_points(prop)
+                .into_iter()
+                .filter(|&(x, y)| x < rows && y < cols)
+                .collect();
+            set_stones(&points, color, &mut board);
+        }
+        let root_hash = hash.update(&board);
+        game.set_root(board.clone(), root_hash);
+
+        // the root may also say who moves first
+        if let Some(player) = root.get("PL").and_then(parse_player) {
+            let setup = Setup {
+                player: Some(player),
+                ..Setup::default()
+            };
+            game.set_setup(&board, root_hash, setup);
         }
-        println!();
     }
+
+    // the root may have a move as well, and territory, annotation and markup like any other node
+    eval_moves(root, board, tree, hash)?;
+
+    // evaluate the nodes and variations after the root, then return to the end of the main line
+    let main_line = load_game_tree(game_tree, 1, board, tree, hash)?;
+    goto_node(main_line, board, tree, hash)
+}
+
+/// Saves the current state of the board
+#[tauri::command]
+fn save_state(tree: tauri::State<Tree>, hash: tauri::State<Hash>) -> Result<(), Error> {
+    let hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
+    game.save_state(hash.clone());
+    Ok(())
 }
 
 /// Reverts to a saved state of the board
@@ -454,44 +1123,43 @@ fn revert_state(
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
     hash: tauri::State<Hash>,
-) -> Vec<(usize, usize, usize)> {
-    let mut board = board.pieces.lock().unwrap();
-    let mut hash = hash.zobrist.lock().unwrap();
-    let mut game = tree.game.lock().unwrap();
+) -> Result<Vec<(usize, usize, usize)>, Error> {
+    let mut board = board.pieces.lock()?;
+    let mut hash = hash.zobrist.lock()?;
+    let mut game = tree.game.lock()?;
     if game.states.len() < state_idx + 1 {
-        return Vec::new();
+        return Ok(Vec::new());
     }
-    let (state, curr, root, saved_hash) = game.states[state_idx].clone();
-    *board = state.to_vec();
-    game.curr = curr.clone();
-    game.root = root.clone();
+    let (node, saved_hash) = game.states[state_idx].clone();
+    *board = game.get_board(node);
+    game.curr = node;
     *hash = saved_hash;
 
     // iterate through board and add pieces
     let mut added: Vec<(usize, usize, usize)> = Vec::new();
-    for i in 0..ROWS {
-        for j in 0..COLS {
-            if let Intersection::Black(_) = board[i][j] {
+    for i in 0..board.len() {
+        for j in 0..board[i].len() {
+            if let Intersection::Black = board[i][j] {
                 added.push((i, j, BLACK));
-            } else if let Intersection::White(_) = board[i][j] {
+            } else if let Intersection::White = board[i][j] {
                 added.push((i, j, WHITE));
             }
         }
     }
 
-    added
+    Ok(added)
 }
 
 /// Initialize a number of states for frontend by returning number of states in backend
 #[tauri::command]
-fn init_states(tree: tauri::State<Tree>) -> usize {
-    let game = tree.game.lock().unwrap();
-    game.states.len()
+fn init_states(tree: tauri::State<Tree>) -> Result<usize, Error> {
+    let game = tree.game.lock()?;
+    Ok(game.states.len())
 }
 
 /// Save the current nodes by saving to a SGF file
 #[tauri::command]
-fn save_sgf(file: &str, tree: tauri::State<Tree>) {
+fn save_sgf(file: &str, tree: tauri::State<Tree>) -> Result<(), Error> {
     // check if file ends in extension .sgf, and if not append
     let file = if file.ends_with(".sgf") {
         file.to_string()
@@ -500,14 +1168,15 @@ fn save_sgf(file: &str, tree: tauri::State<Tree>) {
     };
 
     // save SGF file
-    let game = tree.game.lock().unwrap();
+    let game = tree.game.lock()?;
     let sgf = game.to_sgf();
-    fs::write(file, sgf).expect("Error: cannot write file");
+    fs::write(file, sgf)?;
+    Ok(())
 }
 
 /// Save the current Game by serializing it to JSON
 #[tauri::command]
-fn save_game(file: &str, tree: tauri::State<Tree>) {
+fn save_game(file: &str, tree: tauri::State<Tree>) -> Result<(), Error> {
     // check if file ends in extension .save, and if not append
     let file = if file.ends_with(".save") {
         file.to_string()
@@ -516,10 +1185,11 @@ fn save_game(file: &str, tree: tauri::State<Tree>) {
     };
 
     // serialize Game into JSON and save
-    let game = tree.game.lock().unwrap();
+    let game = tree.game.lock()?;
     let saved_game: Saved = Saved::new(&game);
-    let saved_json: String = serde_json::to_string(&saved_game).unwrap();
-    fs::write(file, saved_json).expect("Error: cannot write file");
+    let saved_json: String = serde_json::to_string(&saved_game)?;
+    fs::write(file, saved_json)?;
+    Ok(())
 }
 
 /// Load a Game from a file containing a serialized Saved struct
@@ -528,50 +1198,80 @@ fn load_game(
     file: &str,
     board: tauri::State<Board>,
     tree: tauri::State<Tree>,
-) -> Vec<(usize, usize, usize)> {
+    hash: tauri::State<Hash>,
+) -> Result<Vec<(usize, usize, usize)>, Error> {
     // deserialize Saved struct from file
-    let saved_json: &str = &fs::read_to_string(file).expect("Error: cannot read file");
-    let saved_game: Saved = serde_json::from_str(saved_json).unwrap();
-
-    // clear the board
-    {
-        let mut board = board.pieces.lock().unwrap();
-        *board = vec![vec![Intersection::Empty; COLS]; ROWS];
-    }
+    let saved_json: &str = &fs::read_to_string(file)?;
+    let saved_game: Saved = serde_json::from_str(saved_json)?;
 
     // import SGF into game
-    {
-        let mut tree = tree.game.lock().unwrap();
-        *tree = Game::new();
-    }
-    let added: Vec<(usize, usize, usize)> = from_sgf(&saved_game.sgf, &board, &tree);
+    let added: Vec<(usize, usize, usize)> = from_sgf(&saved_game.sgf, 0, &board, &tree, &hash)?;
 
-    // import saved states into game
-    let mut game = tree.game.lock().unwrap();
+    // import saved information and states into game
+    let mut game = tree.game.lock()?;
+    if let Some(info) = saved_game.info.clone() {
+        game.info = info;
+    }
     game.add_states(saved_game);
 
-    added
+    Ok(added)
 }
 
 fn main() {
     tauri::Builder::default()
         .manage(Board {
-            pieces: Mutex::new(vec![vec![Intersection::Empty; COLS]; ROWS]),
+            pieces: Mutex::new(empty_board(DEFAULT_SIZE, DEFAULT_SIZE)),
         })
         .manage(Hash {
-            zobrist: Mutex::new(Zobrist::new()),
+            zobrist: Mutex::new(Zobrist::new(DEFAULT_SIZE, DEFAULT_SIZE)),
         })
         .manage(Tree {
-            game: Mutex::new(Game::new()),
+            game: Mutex::new(Game::new(DEFAULT_SIZE, DEFAULT_SIZE)),
         })
         .invoke_handler(tauri::generate_handler![
             get_rows,
             get_cols,
             reset,
+            new_game,
             validate,
             tauri_move,
+            tauri_pass,
+            tauri_setup,
+            get_to_play,
+            is_over,
+            get_captures,
+            get_annotation,
+            set_annotation,
+            get_markup,
+            add_markup,
+            remove_markup,
+            get_ruleset,
+            set_ruleset,
+            set_ko_rule,
+            get_game_info,
+            set_game_info,
+            score_game,
+            toggle_dead,
+            get_territory,
             handle_undo,
             handle_redo,
+            get_variations,
+            set_main_line,
+            get_tree,
+            goto_node_id,
+            goto_path,
+            get_path,
+            goto_move,
+            goto_start,
+            goto_end,
+            goto_branch,
+            delete_node,
+            cut_node,
+            paste_node,
+            has_clipboard,
+            promote_variation,
+            move_variation,
+            list_sgf_file,
             from_sgf_file,
             save_state,
             revert_state,
@@ -583,3 +1283,64 @@ fn main() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::Manager;
+
+    /// Load an SGF game into the state of a mock app, then check the game and board it leaves
+    fn load(sgf: &str, check: impl FnOnce(&Game, &Vec<Vec<Intersection>>)) {
+        let app = tauri::test::mock_app();
+        app.manage(Board {
+            pieces: Mutex::new(empty_board(DEFAULT_SIZE, DEFAULT_SIZE)),
+        });
+        app.manage(Hash {
+            zobrist: Mutex::new(Zobrist::new(DEFAULT_SIZE, DEFAULT_SIZE)),
+        });
+        app.manage(Tree {
+            game: Mutex::new(Game::new(DEFAULT_SIZE, DEFAULT_SIZE)),
+        });
+        let (board, tree, hash) = (app.state::<Board>(), app.state::<Tree>(), app.state::<Hash>());
+        from_sgf(sgf, 0, &board, &tree, &hash).unwrap();
+        let game = tree.game.lock().unwrap();
+        let board = board.pieces.lock().unwrap();
+        check(&game, &board);
+    }
+
+    #[test]
+    fn loads_a_move_in_the_root_node() {
+        load("(;SZ[19]B[pd];W[dp])", |game, board| {
+            let (black, white) = (parse_point("pd").unwrap(), parse_point("dp").unwrap());
+            let end = game.curr_node();
+            assert_eq!(end.get_piece(), Some(white));
+            let first = game.node(end.get_parent().unwrap());
+            assert_eq!(first.get_piece(), Some(black));
+            assert_eq!(first.get_parent(), Some(game.root));
+            assert_eq!(board[black.0][black.1], Intersection::Black);
+            assert_eq!(board[white.0][white.1], Intersection::White);
+        });
+    }
+
+    #[test]
+    fn loads_root_setup_and_annotation_with_its_move() {
+        load("(;SZ[9]AB[aa]AW[bb]AE[cc]C[start]B[cc])", |game, board| {
+            let root = game.get_board(game.root);
+            assert_eq!(root[0][0], Intersection::Black);
+            assert_eq!(root[1][1], Intersection::White);
+            assert_eq!(root[2][2], Intersection::Empty);
+            assert_eq!(game.curr_node().get_parent(), Some(game.root));
+            assert_eq!(game.curr_node().get_annotation().comment.as_deref(), Some("start"));
+            assert_eq!(board[2][2], Intersection::Black);
+        });
+    }
+
+    #[test]
+    fn loads_territory_in_the_root_node() {
+        load("(;SZ[5]AB[ba][ab]AW[aa]TB[aa])", |game, _| {
+            assert_eq!(game.curr, game.root);
+            let dead: HashSet<(usize, usize)> = [(0, 0)].iter().cloned().collect();
+            assert_eq!(game.curr_node().get_dead(), dead);
+        });
+    }
+}
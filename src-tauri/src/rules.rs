@@ -0,0 +1,155 @@
+use crate::go::KoRule;
+use crate::score::Scoring;
+use serde::{Deserialize, Serialize};
+
+/// How handicap stones are placed at the start of a game
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Placement {
+    /// Stones go on the standard star points for the board size
+    Fixed,
+    /// Black places the stones anywhere before white's first move
+    Free,
+}
+
+/// A set of rules that decide legal moves and how the game is scored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ruleset {
+    /// name of the rules, as written to the SGF RU property
+    pub name: String,
+    pub ko: KoRule,
+    /// whether a move may leave its own group without liberties
+    pub suicide: bool,
+    pub scoring: Scoring,
+    /// komi for an even game
+    pub komi: f64,
+    pub placement: Placement,
+}
+
+impl Ruleset {
+    pub fn chinese() -> Ruleset {
+        Ruleset {
+            name: String::from("Chinese"),
+            ko: KoRule::Positional,
+            suicide: false,
+            scoring: Scoring::Area,
+            komi: 7.5,
+            placement: Placement::Free,
+        }
+    }
+
+    pub fn japanese() -> Ruleset {
+        Ruleset {
+            name: String::from("Japanese"),
+            ko: KoRule::Simple,
+            suicide: false,
+            scoring: Scoring::Territory,
+            komi: 6.5,
+            placement: Placement::Fixed,
+        }
+    }
+
+    pub fn aga() -> Ruleset {
+        Ruleset {
+            name: String::from("AGA"),
+            ko: KoRule::Situational,
+            suicide: false,
+            scoring: Scoring::Area,
+            komi: 7.5,
+            placement: Placement::Fixed,
+        }
+    }
+
+    pub fn new_zealand() -> Ruleset {
+        Ruleset {
+            name: String::from("NZ"),
+            ko: KoRule::Situational,
+            suicide: true,
+            scoring: Scoring::Area,
+            komi: 7.0,
+            placement: Placement::Free,
+        }
+    }
+
+    pub fn tromp_taylor() -> Ruleset {
+        Ruleset {
+            name: String::from("Tromp-Taylor"),
+            ko: KoRule::Positional,
+            suicide: true,
+            scoring: Scoring::Area,
+            komi: 7.5,
+            placement: Placement::Free,
+        }
+    }
+
+    pub fn ing() -> Ruleset {
+        Ruleset {
+            name: String::from("GOE"),
+            ko: KoRule::Situational,
+            suicide: true,
+            scoring: Scoring::Area,
+            komi: 7.5,
+            placement: Placement::Fixed,
+        }
+    }
+
+    /// Get the preset for a name of rules, accepting the SGF RU values and common aliases
+    pub fn from_name(name: &str) -> Option<Ruleset> {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "chinese" | "cn" => Some(Ruleset::chinese()),
+            "japanese" | "jp" => Some(Ruleset::japanese()),
+            "aga" => Some(Ruleset::aga()),
+            "nz" | "new zealand" => Some(Ruleset::new_zealand()),
+            "tromp-taylor" | "tromp taylor" | "tt" => Some(Ruleset::tromp_taylor()),
+            "goe" | "ing" => Some(Ruleset::ing()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Ruleset {
+    fn default() -> Ruleset {
+        Ruleset::chinese()
+    }
+}
+
+/// Get the fixed points for a number of handicap stones, or None if the board has no standard
+/// placement for that many stones
+pub fn handicap_points(rows: usize, cols: usize, count: usize) -> Option<Vec<(usize, usize)>> {
+    if count < 2 || rows < 7 || cols < 7 {
+        return None;
+    }
+
+    // star points are on the fourth line, or the third line on small boards
+    let edge = |size: usize| if size >= 13 { 3 } else { 2 };
+    let (top, bottom) = (edge(rows), rows - 1 - edge(rows));
+    let (left, right) = (edge(cols), cols - 1 - edge(cols));
+    let (middle_row, middle_col) = (rows / 2, cols / 2);
+
+    // boards with an even side have no center or side star points
+    let max = if rows % 2 == 1 && cols % 2 == 1 { 9 } else { 4 };
+    if count > max {
+        return None;
+    }
+
+    // corners first, then the sides, with the center for odd counts
+    let mut points = vec![(top, right), (bottom, left)];
+    if count >= 3 {
+        points.push((bottom, right));
+    }
+    if count >= 4 {
+        points.push((top, left));
+    }
+    if count >= 6 {
+        points.push((middle_row, left));
+        points.push((middle_row, right));
+    }
+    if count >= 8 {
+        points.push((top, middle_col));
+        points.push((bottom, middle_col));
+    }
+    if count >= 5 && count % 2 == 1 {
+        points.push((middle_row, middle_col));
+    }
+    Some(points)
+}
@@ -0,0 +1,271 @@
+use crate::game::{BLACK, WHITE};
+use crate::go::Intersection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const EMPTY: usize = 0;
+
+/// Method used to count the score at the end of a game
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Scoring {
+    /// Chinese counting: stones on the board plus surrounded territory
+    Area,
+    /// Japanese counting: surrounded territory plus prisoners
+    Territory,
+}
+
+/// The counted score of a game, with the territory of each player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub black: f64,
+    pub white: f64,
+    pub black_territory: Vec<(usize, usize)>,
+    pub white_territory: Vec<(usize, usize)>,
+    pub result: String,
+}
+
+/// The dead stones and the territory of each player in a position being scored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Territory {
+    pub dead: Vec<(usize, usize)>,
+    pub black: Vec<(usize, usize)>,
+    pub white: Vec<(usize, usize)>,
+}
+
+impl Territory {
+    pub fn new(board: &[Vec<Intersection>], dead: &HashSet<(usize, usize)>) -> Territory {
+        let owners = get_territory(board, dead);
+        let mut black = vec![];
+        let mut white = vec![];
+        for (i, row) in owners.iter().enumerate() {
+            for (j, &owner) in row.iter().enumerate() {
+                match owner {
+                    BLACK => black.push((i, j)),
+                    WHITE => white.push((i, j)),
+                    _ => (),
+                }
+            }
+        }
+        let mut dead: Vec<(usize, usize)> = dead.iter().cloned().collect();
+        dead.sort();
+        Territory { dead, black, white }
+    }
+}
+
+/// Find the owner of every empty intersection after removing dead stones, as a board of colors
+pub fn get_territory(
+    board: &[Vec<Intersection>],
+    dead: &HashSet<(usize, usize)>,
+) -> Vec<Vec<usize>> {
+    let (rows, cols) = (board.len(), board[0].len());
+
+    // get the color of every intersection, treating dead stones as empty
+    let mut colors = vec![vec![EMPTY; cols]; rows];
+    for i in 0..rows {
+        for j in 0..cols {
+            if dead.contains(&(i, j)) {
+                continue;
+            }
+            colors[i][j] = match board[i][j] {
+                Intersection::Black => BLACK,
+                Intersection::White => WHITE,
+                Intersection::Empty => EMPTY,
+            };
+        }
+    }
+
+    // flood fill each empty region, and find which colors border it
+    let mut owners = vec![vec![EMPTY; cols]; rows];
+    let mut visited = vec![vec![false; cols]; rows];
+    for i in 0..rows {
+        for j in 0..cols {
+            if visited[i][j] || colors[i][j] != EMPTY {
+                continue;
+            }
+            let mut region: Vec<(usize, usize)> = vec![];
+            let mut borders = (false, false);
+            let mut queue = vec![(i, j)];
+            visited[i][j] = true;
+            while let Some((x, y)) = queue.pop() {
+                region.push((x, y));
+                let mut neighbors = vec![];
+                if x > 0 { neighbors.push((x - 1, y)); }
+                if x < rows - 1 { neighbors.push((x + 1, y)); }
+                if y > 0 { neighbors.push((x, y - 1)); }
+                if y < cols - 1 { neighbors.push((x, y + 1)); }
+                for (nx, ny) in neighbors {
+                    match colors[nx][ny] {
+                        BLACK => borders.0 = true,
+                        WHITE => borders.1 = true,
+                        _ => {
+                            if !visited[nx][ny] {
+                                visited[nx][ny] = true;
+                                queue.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+            }
+
+            // a region belongs to a player only if it borders their stones alone
+            let owner = match borders {
+                (true, false) => BLACK,
+                (false, true) => WHITE,
+                _ => EMPTY,
+            };
+            for (x, y) in region {
+                owners[x][y] = owner;
+            }
+        }
+    }
+
+    owners
+}
+
+/// Score a board, given the prisoners taken by (black, white) during the game and the dead stones
+pub fn score(
+    board: &[Vec<Intersection>],
+    scoring: Scoring,
+    komi: f64,
+    prisoners: (usize, usize),
+    dead: &HashSet<(usize, usize)>,
+) -> Score {
+    let owners = get_territory(board, dead);
+
+    // count territory, living stones, and dead stones for each player
+    let mut black_territory = vec![];
+    let mut white_territory = vec![];
+    let (mut black_stones, mut white_stones) = (0, 0);
+    let (mut dead_black, mut dead_white) = (0, 0);
+    for i in 0..board.len() {
+        for j in 0..board[i].len() {
+            let is_dead = dead.contains(&(i, j));
+            match (&board[i][j], is_dead) {
+                (Intersection::Black, false) => black_stones += 1,
+                (Intersection::White, false) => white_stones += 1,
+                (Intersection::Black, true) => dead_black += 1,
+                (Intersection::White, true) => dead_white += 1,
+                _ => (),
+            }
+            match owners[i][j] {
+                BLACK => black_territory.push((i, j)),
+                WHITE => white_territory.push((i, j)),
+                _ => (),
+            }
+        }
+    }
+
+    // dead stones are removed from the board, and count as prisoners under territory scoring
+    let (black, white) = match scoring {
+        Scoring::Area => (
+            (black_territory.len() + black_stones) as f64,
+            (white_territory.len() + white_stones) as f64 + komi,
+        ),
+        Scoring::Territory => (
+            (black_territory.len() + prisoners.0 + dead_white) as f64,
+            (white_territory.len() + prisoners.1 + dead_black) as f64 + komi,
+        ),
+    };
+
+    Score {
+        black,
+        white,
+        black_territory,
+        white_territory,
+        result: get_result(black, white),
+    }
+}
+
+/// Format the difference between two scores as an SGF result
+pub fn get_result(black: f64, white: f64) -> String {
+    if black > white {
+        format!("B+{}", black - white)
+    } else if white > black {
+        format!("W+{}", white - black)
+    } else {
+        String::from("0")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a board from rows of B, W and . characters
+    fn board(rows: &[&str]) -> Vec<Vec<Intersection>> {
+        rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        'B' => Intersection::Black,
+                        'W' => Intersection::White,
+                        _ => Intersection::Empty,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A wall of black stones and a wall of white stones, with a dead white stone inside
+    /// black's territory
+    fn walls() -> (Vec<Vec<Intersection>>, HashSet<(usize, usize)>) {
+        let board = board(&[".BW..", ".BW..", "WBW..", ".BW..", ".BW.."]);
+        let dead = [(2, 0)].iter().cloned().collect();
+        (board, dead)
+    }
+
+    #[test]
+    fn dead_stones_count_as_territory_of_the_other_player() {
+        let (board, dead) = walls();
+        let owners = get_territory(&board, &dead);
+        assert_eq!(owners[2][0], BLACK);
+        assert_eq!(owners[0][0], BLACK);
+        assert_eq!(owners[0][3], WHITE);
+        assert_eq!(owners[0][1], EMPTY);
+
+        let territory = Territory::new(&board, &dead);
+        assert_eq!(territory.dead, vec![(2, 0)]);
+        assert_eq!(territory.black.len(), 5);
+        assert_eq!(territory.white.len(), 10);
+    }
+
+    #[test]
+    fn area_scoring_counts_living_stones_and_territory() {
+        let (board, dead) = walls();
+        let score = score(&board, Scoring::Area, 0.5, (2, 1), &dead);
+        assert_eq!(score.black, 10.0);
+        assert_eq!(score.white, 15.5);
+        assert_eq!(score.black_territory.len(), 5);
+        assert_eq!(score.white_territory.len(), 10);
+        assert_eq!(score.result, "W+5.5");
+    }
+
+    #[test]
+    fn territory_scoring_counts_prisoners_and_dead_stones() {
+        let (board, dead) = walls();
+        let score = score(&board, Scoring::Territory, 0.5, (2, 1), &dead);
+        assert_eq!(score.black, 8.0);
+        assert_eq!(score.white, 11.5);
+        assert_eq!(score.result, "W+3.5");
+    }
+
+    #[test]
+    fn regions_bordering_both_colors_are_neutral() {
+        let board = board(&["B.W", "B.W", "B.W"]);
+        let owners = get_territory(&board, &HashSet::new());
+        assert!(owners.iter().all(|row| row[1] == EMPTY));
+
+        let score = score(&board, Scoring::Area, 0.0, (0, 0), &HashSet::new());
+        assert!(score.black_territory.is_empty());
+        assert!(score.white_territory.is_empty());
+        assert_eq!((score.black, score.white), (3.0, 3.0));
+        assert_eq!(score.result, "0");
+    }
+
+    #[test]
+    fn formats_results_as_sgf() {
+        assert_eq!(get_result(7.0, 0.5), "B+6.5");
+        assert_eq!(get_result(3.0, 10.0), "W+7");
+        assert_eq!(get_result(6.5, 6.5), "0");
+    }
+}
@@ -0,0 +1,494 @@
+use std::fmt;
+
+/// A property of an SGF node, with its identifier and its values
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Property {
+    pub id: String,
+    pub values: Vec<String>,
+}
+
+/// A node of an SGF game tree, holding its properties in the order they appear
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SgfNode {
+    pub properties: Vec<Property>,
+}
+
+/// An SGF game tree, as a sequence of nodes followed by the variations that branch from the last
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct GameTree {
+    pub nodes: Vec<SgfNode>,
+    pub variations: Vec<GameTree>,
+}
+
+/// An error in an SGF string, with the line and column where it was found
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SgfError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SgfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl SgfNode {
+    /// Get the first value of a property, if present
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.get_all(id).first().map(|value| value.as_str())
+    }
+
+    /// Get all the values of a property, which is empty if the property is not present
+    pub fn get_all(&self, id: &str) -> &[String] {
+        self.properties
+            .iter()
+            .find(|property| property.id == id)
+            .map(|property| property.values.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Check if the node has a property
+    pub fn has(&self, id: &str) -> bool {
+        self.properties.iter().any(|property| property.id == id)
+    }
+
+    /// Get the board coordinates of a list of points, expanding compressed rectangles like aa:cc
+    pub fn get_points(&self, id: &str) -> Vec<(usize, usize)> {
+        let mut points = vec![];
+        for value in self.get_all(id) {
+            match value.split_once(':') {
+                Some((from, to)) => {
+                    if let (Some(from), Some(to)) = (parse_point(from), parse_point(to)) {
+                        for x in from.0.min(to.0)..=from.0.max(to.0) {
+                            for y in from.1.min(to.1)..=from.1.max(to.1) {
+                                points.push((x, y));
+                            }
+                        }
+                    }
+                }
+                None => points.extend(parse_point(value)),
+            }
+        }
+        points
+    }
+}
+
+/// Reader over the characters of an SGF string that keeps track of the current line and column
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Reader<'a> {
+        Reader {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn error(&self, message: &str) -> SgfError {
+        SgfError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    /// Read a collection of game trees
+    fn collection(&mut self) -> Result<Vec<GameTree>, SgfError> {
+        // anything before the first game tree is ignored, as files often start with a header
+        while matches!(self.peek(), Some(c) if c != '(') {
+            self.next();
+        }
+        let mut trees = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('(') => trees.push(self.game_tree()?),
+                Some(_) if !trees.is_empty() => break,
+                Some(_) => return Err(self.error("Expected '(' to start a game tree")),
+                None => break,
+            }
+        }
+        if trees.is_empty() {
+            return Err(self.error("No game tree found"));
+        }
+        Ok(trees)
+    }
+
+    /// Read a game tree, starting at its opening parenthesis
+    fn game_tree(&mut self) -> Result<GameTree, SgfError> {
+        self.next();
+        let mut tree = GameTree::default();
+
+        // read the sequence of nodes
+        self.skip_whitespace();
+        while self.peek() == Some(';') {
+            self.next();
+            tree.nodes.push(self.node()?);
+            self.skip_whitespace();
+        }
+        if tree.nodes.is_empty() {
+            return Err(self.error("Expected ';' to start a node"));
+        }
+
+        // read the variations, until the closing parenthesis
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('(') => tree.variations.push(self.game_tree()?),
+                Some(')') => {
+                    self.next();
+                    return Ok(tree);
+                }
+                Some(_) => return Err(self.error("Expected '(' or ')' after a node")),
+                None => return Err(self.error("Missing ')' at the end of a game tree")),
+            }
+        }
+    }
+
+    /// Read the properties of a node, after its semicolon
+    fn node(&mut self) -> Result<SgfNode, SgfError> {
+        let mut node = SgfNode::default();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let property = self.property()?;
+
+                    // a repeated identifier adds to the values of the earlier property
+                    match node.properties.iter_mut().find(|p| p.id == property.id) {
+                        Some(existing) => existing.values.extend(property.values),
+                        None => node.properties.push(property),
+                    }
+                }
+                _ => return Ok(node),
+            }
+        }
+    }
+
+    /// Read a property identifier and its values
+    fn property(&mut self) -> Result<Property, SgfError> {
+        // old versions of SGF allow lowercase letters in identifiers, which are ignored
+        let mut id = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+            self.next();
+            if c.is_ascii_uppercase() {
+                id.push(c);
+            }
+        }
+        if id.is_empty() {
+            return Err(self.error("Property identifier has no uppercase letters"));
+        }
+
+        let mut values = vec![];
+        self.skip_whitespace();
+        while self.peek() == Some('[') {
+            values.push(self.value()?);
+            self.skip_whitespace();
+        }
+        if values.is_empty() {
+            return Err(self.error(&format!("Property {} has no value", id)));
+        }
+        Ok(Property { id, values })
+    }
+
+    /// Read a bracketed property value, resolving escapes and soft line breaks
+    fn value(&mut self) -> Result<String, SgfError> {
+        let (line, column) = (self.line, self.column);
+        self.next();
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(']') => return Ok(value),
+                Some('\\') => match self.next() {
+                    // a soft line break is removed, along with the other half of a \r\n pair
+                    Some('\n') => {
+                        if self.peek() == Some('\r') {
+                            self.next();
+                        }
+                    }
+                    Some('\r') => {
+                        if self.peek() == Some('\n') {
+                            self.next();
+                        }
+                    }
+                    Some(c) => value.push(c),
+                    None => break,
+                },
+                Some('\r') => {
+                    // normalize line breaks to \n
+                    if self.peek() == Some('\n') {
+                        self.next();
+                    }
+                    value.push('\n');
+                }
+                Some(c) if c.is_whitespace() && c != '\n' => value.push(' '),
+                Some(c) => value.push(c),
+                None => break,
+            }
+        }
+        Err(SgfError {
+            line,
+            column,
+            message: String::from("Missing ']' at the end of a property value"),
+        })
+    }
+}
+
+/// Parse an SGF string into its collection of game trees
+pub fn parse(text: &str) -> Result<Vec<GameTree>, SgfError> {
+    Reader::new(text).collection()
+}
+
+/// Escape the characters of a text value that would end it early in SGF
+pub fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+/// Convert a board index to an SGF coordinate letter, using a-z then A-Z
+pub fn to_sgf_coord(idx: usize) -> char {
+    if idx < 26 {
+        (idx as u8 + b'a') as char
+    } else {
+        (idx as u8 - 26 + b'A') as char
+    }
+}
+
+/// Convert a move to an SGF point value, where a pass is written as an empty value
+pub fn sgf_point(piece: Option<(usize, usize)>) -> String {
+    match piece {
+        Some((row, col)) => format!("{}{}", to_sgf_coord(col), to_sgf_coord(row)),
+        None => String::new(),
+    }
+}
+
+/// Convert an SGF coordinate letter to a board index
+pub fn from_sgf_coord(c: char) -> Option<usize> {
+    match c {
+        'a'..='z' => Some(c as usize - 'a' as usize),
+        'A'..='Z' => Some(c as usize - 'A' as usize + 26),
+        _ => None,
+    }
+}
+
+/// Transform an SGF point into board coordinates
+pub fn parse_point(coords: &str) -> Option<(usize, usize)> {
+    let mut chars = coords.chars().map(from_sgf_coord);
+    let y = chars.next()??;
+    let x = chars.next()??;
+    if chars.next().is_some() {
+        return None;
+    }
+    Some((x, y))
+}
+
+/// Parse the board dimensions from the value of an SGF SZ property as (rows, cols)
+pub fn parse_size(value: &str, max: usize) -> Option<(usize, usize)> {
+    let (cols, rows) = match value.split_once(':') {
+        Some((cols, rows)) => (cols.trim().parse().ok()?, rows.trim().parse().ok()?),
+        None => {
+            let size: usize = value.trim().parse().ok()?;
+            (size, size)
+        }
+    };
+    if rows == 0 || cols == 0 || rows > max || cols > max {
+        return None;
+    }
+    Some((rows, cols))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_node(text: &str) -> SgfNode {
+        parse(text).unwrap()[0].nodes[0].clone()
+    }
+
+    #[test]
+    fn parses_a_simple_game() {
+        let trees = parse("(;FF[4]GM[1]SZ[19];B[pd];W[dp])").unwrap();
+        assert_eq!(trees.len(), 1);
+        assert_eq!(trees[0].nodes.len(), 3);
+        assert_eq!(trees[0].nodes[0].get("SZ"), Some("19"));
+        assert_eq!(trees[0].nodes[1].get("B"), Some("pd"));
+        assert_eq!(trees[0].nodes[2].get("W"), Some("dp"));
+    }
+
+    #[test]
+    fn ignores_whitespace_between_tokens() {
+        let trees = parse("\n  ( ;FF[4]\r\n GM [1]\n;\tB[pd]\n ; W[dp] )\n").unwrap();
+        assert_eq!(trees[0].nodes.len(), 3);
+        assert_eq!(trees[0].nodes[0].get("GM"), Some("1"));
+        assert_eq!(trees[0].nodes[2].get("W"), Some("dp"));
+    }
+
+    #[test]
+    fn ignores_text_before_the_first_game_tree() {
+        let trees = parse("header text; with a semicolon\n(;GM[1])").unwrap();
+        assert_eq!(trees[0].nodes[0].get("GM"), Some("1"));
+    }
+
+    #[test]
+    fn keeps_special_characters_inside_values() {
+        let node = first_node("(;C[first; (second) ;third])");
+        assert_eq!(node.get("C"), Some("first; (second) ;third"));
+    }
+
+    #[test]
+    fn resolves_escapes() {
+        let node = first_node(r"(;C[a \] bracket, a \\ backslash, and \: a colon])");
+        assert_eq!(node.get("C"), Some(r"a ] bracket, a \ backslash, and : a colon"));
+    }
+
+    #[test]
+    fn removes_soft_line_breaks() {
+        let node = first_node("(;C[soft\\\nbreak\nhard break])");
+        assert_eq!(node.get("C"), Some("softbreak\nhard break"));
+        let node = first_node("(;C[soft\\\r\nbreak\r\nhard break])");
+        assert_eq!(node.get("C"), Some("softbreak\nhard break"));
+    }
+
+    #[test]
+    fn converts_other_whitespace_to_spaces() {
+        let node = first_node("(;C[tab\there])");
+        assert_eq!(node.get("C"), Some("tab here"));
+    }
+
+    #[test]
+    fn reads_multiple_properties_per_node() {
+        let node = first_node("(;B[aa]C[comment]N[name]BM[1])");
+        assert_eq!(node.properties.len(), 4);
+        assert_eq!(node.get("B"), Some("aa"));
+        assert_eq!(node.get("C"), Some("comment"));
+        assert_eq!(node.get("N"), Some("name"));
+        assert_eq!(node.get("BM"), Some("1"));
+    }
+
+    #[test]
+    fn reads_multiple_values() {
+        let node = first_node("(;AB[aa][bb] [cc]\n[dd]AW[ee])");
+        assert_eq!(node.get_all("AB"), ["aa", "bb", "cc", "dd"]);
+        assert_eq!(node.get_points("AB"), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(node.get_points("AW"), vec![(4, 4)]);
+        assert!(node.get_all("AE").is_empty());
+    }
+
+    #[test]
+    fn expands_compressed_point_lists() {
+        let node = first_node("(;AB[aa:bc][dd]AE[cb:ba])");
+        assert_eq!(
+            node.get_points("AB"),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1), (3, 3)]
+        );
+        assert_eq!(node.get_points("AE"), vec![(0, 1), (0, 2), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn keeps_unknown_properties() {
+        let node = first_node("(;GM[1]XYZ[anything]MULTIGOGM[1])");
+        assert_eq!(node.get("XYZ"), Some("anything"));
+        assert_eq!(node.get("MULTIGOGM"), Some("1"));
+    }
+
+    #[test]
+    fn ignores_lowercase_letters_in_old_identifiers() {
+        let node = first_node("(;AddBlack[aa]Comment[old style])");
+        assert_eq!(node.get("AB"), Some("aa"));
+        assert_eq!(node.get("C"), Some("old style"));
+    }
+
+    #[test]
+    fn merges_repeated_identifiers() {
+        let node = first_node("(;AB[aa]AW[bb]AB[cc])");
+        assert_eq!(node.get_all("AB"), ["aa", "cc"]);
+    }
+
+    #[test]
+    fn reads_empty_values() {
+        let node = first_node("(;B[]DO[])");
+        assert_eq!(node.get("B"), Some(""));
+        assert!(node.has("DO"));
+    }
+
+    #[test]
+    fn parses_variations() {
+        let trees = parse("(;GM[1];B[aa](;W[bb];B[cc])(;W[dd](;B[ee])(;B[ff])))").unwrap();
+        let tree = &trees[0];
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.variations.len(), 2);
+        assert_eq!(tree.variations[0].nodes.len(), 2);
+        assert_eq!(tree.variations[0].nodes[1].get("B"), Some("cc"));
+        assert_eq!(tree.variations[1].nodes[0].get("W"), Some("dd"));
+        assert_eq!(tree.variations[1].variations.len(), 2);
+        assert_eq!(tree.variations[1].variations[1].nodes[0].get("B"), Some("ff"));
+    }
+
+    #[test]
+    fn parses_collections() {
+        let trees = parse("(;GM[1]PB[first])\n(;GM[1]PB[second])(;GM[1]PB[third])").unwrap();
+        assert_eq!(trees.len(), 3);
+        assert_eq!(trees[1].nodes[0].get("PB"), Some("second"));
+        assert_eq!(trees[2].nodes[0].get("PB"), Some("third"));
+    }
+
+    #[test]
+    fn reports_the_location_of_errors() {
+        let err = parse("(;GM[1]\n;B[aa]\n;W[bb").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 3));
+
+        let err = parse("(;GM[1]\n;B[aa]").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "Missing ')' at the end of a game tree");
+
+        let err = parse("(;GM[1];B)").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 10));
+
+        let err = parse("(GM[1])").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 2));
+
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn converts_points_and_sizes() {
+        assert_eq!(parse_point("pd"), Some((3, 15)));
+        assert_eq!(parse_point("Aa"), Some((0, 26)));
+        assert_eq!(parse_point("a"), None);
+        assert_eq!(parse_point("a1"), None);
+        assert_eq!(sgf_point(Some((3, 15))), "pd");
+        assert_eq!(sgf_point(Some((0, 26))), "Aa");
+        assert_eq!(sgf_point(None), "");
+        assert_eq!(parse_size("19", 52), Some((19, 19)));
+        assert_eq!(parse_size("13:9", 52), Some((9, 13)));
+        assert_eq!(parse_size("60", 52), None);
+        assert_eq!(parse_size("x", 52), None);
+    }
+}